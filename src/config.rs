@@ -1,67 +1,627 @@
 #![allow(dead_code)]
-use std::{collections::HashMap, sync::LazyLock};
-
-use serde_repr::{Deserialize_repr, Serialize_repr};
-
-pub const ADDRESS: &str = "0.0.0.0:8899";
-// pub const AUTH_TOKEN: &str = "a1fe0d2d2469bb472016d667be975b51";
-
-pub const CDN_FRAMES_PATH: &str = "../asset/private/frame";
-pub const CDN_CHARACTER_IMAGES_PATH: &str = "../asset/private/idol";
-pub const CDN_CARD_IMAGES_PATH: &str = "../asset/private/custom-card-art";
-pub const CDN_RENDERS_PATH: &str = "../asset/public/render";
-
-pub const RENDER_TIMEOUT: f32 = 5.0; // in seconds
-
-pub const FAN_CARD_ANGLE: f32 = 5.0;
-pub const FAN_CIRCLE_CENTER_DISTANCE: f32 = 3000.0;
-
-pub const ALBUM_CARD_PADDING: u32 = 10;
-
-pub const FRAME_TABLE: LazyLock<HashMap<FrameType, FrameDetails>> = LazyLock::new(|| {
-    HashMap::from([
-        (FrameType::MoonweaverFrame, FrameDetails {
-            name: "moonweaver",
-            static_model: true,
-            color_model: true,
-            extendable: true, // whether can be kindled (has that improved version)
-            width: 550,
-            height: 800
-        }),
-        (FrameType::EssentiaFrame, FrameDetails {
-            name: "essentia",
-            static_model: false,
-            color_model: true,
-            extendable: false,
-            width: 550,
-            height: 800
-        }),
-    ])
-});
-
-#[repr(u8)]
-#[derive(Eq, PartialEq, Hash, Serialize_repr, Deserialize_repr, Debug, Clone)]
-pub enum FrameType {
-    MoonweaverFrame = 0,
-    EssentiaFrame = 1,
-}
-
-impl ToString for FrameType {
-    fn to_string(&self) -> String {
+use std::{fmt, net::SocketAddr, path::Path, str::FromStr};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_CONFIG_PATH: &str = "hagaki.toml";
+const ENV_PREFIX: &str = "HAGAKI_";
+
+const USAGE: &str = "Usage: hagaki [OPTIONS]
+
+Options:
+  --config <path>                      Path to TOML config file (default: hagaki.toml)
+  --address <addr>                     Socket address to listen on
+  --paths-frames <path>                Directory with frame assets
+  --paths-character-images <path>      Directory with character art
+  --paths-card-images <path>           Directory with custom card art
+  --paths-renders <path>               Directory where saved renders are written (local storage)
+  --paths-cache <path>                 Directory of content-addressed disk cache of renders (empty disables it)
+  --render-timeout <seconds>           Maximum time single render can take
+  --render-card-timeout <seconds>      Maximum time of card render (defaults to render timeout)
+  --render-fan-timeout <seconds>       Maximum time of fan render (defaults to render timeout)
+  --render-album-timeout <seconds>     Maximum time of album render (defaults to render timeout)
+  --render-fan-card-angle <degrees>    Angle between neighbouring cards in a fan
+  --render-fan-circle-center-distance <px>
+                                       Radius of the circle fan cards are placed on
+  --render-album-card-padding <px>     Padding between cards in an album
+  --render-threads <count>             Size of render thread pool (0 uses all CPU cores)
+  --render-queue-size <count>          Maximum number of renders running or waiting at once
+  --render-retry-after <seconds>       Retry-After sent when render queue is full
+  --frames-watch-interval <seconds>    How often to check frames directory for changes (0 disables)
+  --frames-strict <true|false>         Refuse to start when any frame fails validation
+  --art-fallback <list>                Comma separated fallback chain for missing art (base, placeholder)
+  --art-placeholder <path>             Placeholder art, relative to character images directory
+  --cache-art-memory <MiB>             Memory budget for decoded character art (0 disables cache)
+  --cache-mask-memory <MiB>            Memory budget for recolored frame masks (0 disables cache)
+  --cache-response-memory <MiB>        Memory budget for encoded renders (0 disables cache)
+  --http-cache-control <value>         Cache-Control header of render responses (empty disables it)
+  --limits-max-fan-cards <count>       Maximum number of cards in a fan
+  --limits-max-album-cards <count>     Maximum number of cards in an album
+  --limits-max-pixels <count>          Maximum number of pixels of single render output
+  --limits-max-request-size <bytes>    Maximum size of decoded render hash or POST body
+  --retention-max-size <MiB>           Maximum total size of saved renders (0 disables limit)
+  --retention-max-age <seconds>        Maximum age of saved render (0 disables limit)
+  --retention-interval <seconds>       How often to enforce retention of saved renders (0 only on demand)
+  --storage-backend <local|s3>         Where saved renders are stored
+  --storage-endpoint <url>             S3 endpoint, e.g. https://s3.eu-central-1.amazonaws.com
+  --storage-bucket <name>              S3 bucket
+  --storage-region <region>            S3 region used in request signatures
+  --storage-access-key <key>           S3 access key
+  --storage-secret-key <key>           S3 secret key
+  --storage-prefix <prefix>            Prepended to every S3 object key, e.g. render/
+  --storage-path-style <true|false>    Address bucket as endpoint path instead of subdomain
+  --auth-secret <secret>               Shared secret used to sign render requests
+  --auth-require-signatures <bool>     Reject unsigned render requests
+  --auth-admin-token <token>           Bearer token of admin API (admin API is disabled when not set)
+  -h, --help                           Print this message
+
+Every option is named after its config file section and key (render.timeout -> --render-timeout) and can also be
+set with HAGAKI_* environment variable (e.g. HAGAKI_RENDER_TIMEOUT=5). Unknown environment variables are logged as warnings and ignored.
+Precedence: CLI flags > environment variables > config file > defaults.";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: String,
+    pub paths: PathsConfig,
+    pub render: RenderConfig,
+    pub frames: FramesConfig,
+    pub art: ArtConfig,
+    pub cache: CacheConfig,
+    pub http: HttpConfig,
+    pub limits: LimitsConfig,
+    pub retention: RetentionConfig,
+    pub storage: StorageConfig,
+    pub auth: AuthConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    pub frames: String,
+    pub character_images: String,
+    pub card_images: String,
+    pub renders: String,
+    pub cache: Option<String>, // content-addressed disk cache of every render, disabled when not set
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    pub timeout: f32, // in seconds
+    pub card_timeout: Option<f32>, // in seconds, overrides timeout of card renders
+    pub fan_timeout: Option<f32>, // in seconds, overrides timeout of fan renders
+    pub album_timeout: Option<f32>, // in seconds, overrides timeout of album renders
+    pub fan_card_angle: f32,
+    pub fan_circle_center_distance: f32,
+    pub album_card_padding: u32,
+    pub threads: usize, // size of dedicated render thread pool, 0 uses one thread per CPU core
+    pub queue_size: usize, // renders running or waiting at once, requests above are rejected with 503
+    pub retry_after: u64, // in seconds, sent in Retry-After header when render queue is full
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FramesConfig {
+    pub watch_interval: u64, // in seconds, 0 disables watching frames directory (SIGHUP still reloads frames)
+    pub strict: bool, // refuse to start (or reload) when any frame fails validation, otherwise only broken frames are disabled
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArtConfig {
+    pub base: String, // path of base art (variant 0), relative to character images directory
+    pub variants: Vec<VariantRule>, // first rule matching requested variant wins
+    pub fallback: Vec<ArtFallback>, // tried in order when requested art is missing
+    pub placeholder: Option<String>, // path of placeholder silhouette, relative to character images directory
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    pub art_memory: usize, // in MiB, memory budget for decoded character art, 0 disables cache
+    pub mask_memory: usize, // in MiB, memory budget for recolored frame masks, 0 disables cache
+    pub response_memory: usize, // in MiB, memory budget for encoded renders, 0 disables cache
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub cache_control: String, // Cache-Control header of render responses, empty disables it
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_fan_cards: usize,
+    pub max_album_cards: usize,
+    pub max_pixels: u64, // of single render output (canvas is estimated from frame sizes before rendering)
+    pub max_request_size: usize, // in bytes, of decoded (and decompressed) render hash or POST body
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub max_size: u64, // in MiB, of all saved renders together, least recently accessed are evicted first, 0 (default) disables limit
+    pub max_age: u64, // in seconds since render was saved, 0 disables limit
+    pub interval: u64, // in seconds between background runs, 0 only runs it on startup, when size limit is hit and on demand
+}
+
+/// S3 settings are only used by `s3` backend, `local` one stores renders in `paths.renders`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    pub endpoint: Option<String>,
+    pub bucket: Option<String>,
+    pub region: String,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub prefix: String, // prepended to every object key
+    pub path_style: bool, // `{endpoint}/{bucket}/{key}` instead of `{bucket}.{endpoint}/{key}` (MinIO and most S3-compatible services)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    Local,
+    S3,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub secret: Option<String>, // shared secret used to sign render requests (HMAC-SHA256)
+    pub require_signatures: bool, // reject unsigned render requests
+    pub admin_token: Option<String>, // bearer token of admin API, admin API is disabled when not set
+}
+
+/// Path patterns can use `{id}` and `{variant}` placeholders.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VariantRule {
+    pub min: u8,
+    pub max: u8,
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtFallback {
+    Base,
+    Placeholder,
+}
+
+impl FromStr for ArtFallback {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base" => Ok(ArtFallback::Base),
+            "placeholder" => Ok(ArtFallback::Placeholder),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(StorageBackend::Local),
+            "s3" => Ok(StorageBackend::S3),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: "0.0.0.0:8899".to_string(),
+            paths: PathsConfig::default(),
+            render: RenderConfig::default(),
+            frames: FramesConfig::default(),
+            art: ArtConfig::default(),
+            cache: CacheConfig::default(),
+            http: HttpConfig::default(),
+            limits: LimitsConfig::default(),
+            retention: RetentionConfig::default(),
+            storage: StorageConfig::default(),
+            auth: AuthConfig::default(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            art_memory: 256,
+            mask_memory: 128,
+            response_memory: 128,
+        }
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            // Render of given URL only changes when assets do, so it can be cached for a while
+            cache_control: "public, max-age=86400".to_string(),
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            max_fan_cards: 20,
+            max_album_cards: 100,
+            max_pixels: 50_000_000,
+            max_request_size: 64 * 1024,
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            max_size: 0,
+            max_age: 0,
+            interval: 3600,
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            backend: StorageBackend::Local,
+            endpoint: None,
+            bucket: None,
+            region: "us-east-1".to_string(),
+            access_key: None,
+            secret_key: None,
+            prefix: String::new(),
+            path_style: true,
+        }
+    }
+}
+
+impl Default for ArtConfig {
+    fn default() -> Self {
+        ArtConfig {
+            base: "{id}.png".to_string(),
+            variants: vec![
+                VariantRule { min: 1, max: 9, path: "{id}/u{variant}.png".to_string() },
+                VariantRule { min: 10, max: 255, path: "{id}/x{variant}.png".to_string() },
+            ],
+            fallback: Vec::new(),
+            placeholder: None,
+        }
+    }
+}
+
+impl Default for FramesConfig {
+    fn default() -> Self {
+        FramesConfig {
+            watch_interval: 0,
+            strict: true,
+        }
+    }
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        PathsConfig {
+            frames: "../asset/private/frame".to_string(),
+            character_images: "../asset/private/idol".to_string(),
+            card_images: "../asset/private/custom-card-art".to_string(),
+            renders: "../asset/public/render".to_string(),
+            cache: None,
+        }
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            timeout: 5.0,
+            card_timeout: None,
+            fan_timeout: None,
+            album_timeout: None,
+            fan_card_angle: 5.0,
+            fan_circle_center_distance: 3000.0,
+            album_card_padding: 10,
+            threads: 0,
+            queue_size: 64,
+            retry_after: 1,
+        }
+    }
+}
+
+impl Config {
+    /// Builds final configuration from (in order of precedence) CLI flags, `HAGAKI_*` env vars,
+    /// config file and built-in defaults. Returned config is already validated.
+    pub fn load() -> Result<Config, String> {
+        let flags = parse_flags(std::env::args().skip(1))?;
+
+        let explicit_path = flags.iter()
+            .find(|(key, _)| key == "config")
+            .map(|(_, value)| value.clone())
+            .or_else(|| std::env::var(format!("{}CONFIG", ENV_PREFIX)).ok());
+        let path = explicit_path.clone().unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_string());
+
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(content) => toml::from_str::<Config>(&content).map_err(|e| format!("failed to parse config file {}: {}", path, e))?,
+            // Config file is optional unless user pointed at specific one
+            Err(e) if explicit_path.is_none() && e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(format!("failed to read config file {}: {}", path, e)),
+        };
+
+        for (key, value) in std::env::vars() {
+            let Some(key) = key.strip_prefix(ENV_PREFIX) else { continue };
+            if key == "CONFIG" {
+                continue;
+            }
+            match config.set(&key.to_lowercase(), &value) {
+                Ok(()) => (),
+                // Environment is shared with other programs (and older or newer versions of this one), so typo here
+                // only costs the override
+                Err(SetError::UnknownOption) => warn!("Ignoring {}{} environment variable, there is no such option", ENV_PREFIX, key),
+                Err(e) => return Err(format!("invalid {}{} environment variable: {}", ENV_PREFIX, key, e)),
+            }
+        }
+
+        for (key, value) in flags {
+            if key == "config" {
+                continue;
+            }
+            config.set(&key, &value).map_err(|e| format!("invalid --{} flag: {}", key, e))?;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Keys are `{section}_{key}` of config file (dashes and underscores are interchangeable).
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SetError> {
+        match key.replace('-', "_").as_str() {
+            "address" => self.address = value.to_string(),
+            "paths_frames" => self.paths.frames = value.to_string(),
+            "paths_character_images" => self.paths.character_images = value.to_string(),
+            "paths_card_images" => self.paths.card_images = value.to_string(),
+            "paths_renders" => self.paths.renders = value.to_string(),
+            "paths_cache" => self.paths.cache = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "render_timeout" => self.render.timeout = parse_value(value)?,
+            "render_card_timeout" => self.render.card_timeout = parse_optional(value)?,
+            "render_fan_timeout" => self.render.fan_timeout = parse_optional(value)?,
+            "render_album_timeout" => self.render.album_timeout = parse_optional(value)?,
+            "render_fan_card_angle" => self.render.fan_card_angle = parse_value(value)?,
+            "render_fan_circle_center_distance" => self.render.fan_circle_center_distance = parse_value(value)?,
+            "render_album_card_padding" => self.render.album_card_padding = parse_value(value)?,
+            "render_threads" => self.render.threads = parse_value(value)?,
+            "render_queue_size" => self.render.queue_size = parse_value(value)?,
+            "render_retry_after" => self.render.retry_after = parse_value(value)?,
+            "frames_watch_interval" => self.frames.watch_interval = parse_value(value)?,
+            "frames_strict" => self.frames.strict = parse_value(value)?,
+            "art_fallback" => {
+                self.art.fallback = value.split(',')
+                    .map(|part| part.trim())
+                    .filter(|part| !part.is_empty())
+                    .map(parse_value)
+                    .collect::<Result<_, _>>()?
+            }
+            "cache_art_memory" => self.cache.art_memory = parse_value(value)?,
+            "cache_mask_memory" => self.cache.mask_memory = parse_value(value)?,
+            "cache_response_memory" => self.cache.response_memory = parse_value(value)?,
+            "http_cache_control" => self.http.cache_control = value.trim().to_string(),
+            "limits_max_fan_cards" => self.limits.max_fan_cards = parse_value(value)?,
+            "limits_max_album_cards" => self.limits.max_album_cards = parse_value(value)?,
+            "limits_max_pixels" => self.limits.max_pixels = parse_value(value)?,
+            "limits_max_request_size" => self.limits.max_request_size = parse_value(value)?,
+            "retention_max_size" => self.retention.max_size = parse_value(value)?,
+            "retention_max_age" => self.retention.max_age = parse_value(value)?,
+            "retention_interval" => self.retention.interval = parse_value(value)?,
+            "storage_backend" => self.storage.backend = parse_value(value)?,
+            "storage_endpoint" => self.storage.endpoint = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "storage_bucket" => self.storage.bucket = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "storage_region" => self.storage.region = value.to_string(),
+            "storage_access_key" => self.storage.access_key = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "storage_secret_key" => self.storage.secret_key = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "storage_prefix" => self.storage.prefix = value.to_string(),
+            "storage_path_style" => self.storage.path_style = parse_value(value)?,
+            "auth_secret" => self.auth.secret = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "auth_require_signatures" => self.auth.require_signatures = parse_value(value)?,
+            "auth_admin_token" => self.auth.admin_token = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "art_placeholder" => self.art.placeholder = Some(value.to_string()).filter(|value| !value.is_empty()),
+            _ => return Err(SetError::UnknownOption),
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+
+        if self.address.parse::<SocketAddr>().is_err() {
+            problems.push(format!("address \"{}\" is not a valid socket address", self.address));
+        }
+
+        for (name, path) in [
+            ("paths.frames", &self.paths.frames),
+            ("paths.character_images", &self.paths.character_images),
+        ].into_iter()
+            .chain(Some(("paths.renders", &self.paths.renders)).filter(|_| self.storage.backend == StorageBackend::Local))
+            .chain(self.paths.cache.iter().map(|path| ("paths.cache", path))) {
+            if !Path::new(path).is_dir() {
+                problems.push(format!("{} \"{}\" is not an existing directory", name, path));
+            }
+        }
+        // Custom card art is optional, cards asking for it fail with asset_missing when it's not there
+        if !Path::new(&self.paths.card_images).is_dir() {
+            warn!("paths.card_images \"{}\" is not an existing directory, custom art cards won't render", self.paths.card_images);
+        }
+
+        for (name, timeout) in [
+            ("render.timeout", Some(self.render.timeout)),
+            ("render.card_timeout", self.render.card_timeout),
+            ("render.fan_timeout", self.render.fan_timeout),
+            ("render.album_timeout", self.render.album_timeout),
+        ] {
+            if let Some(timeout) = timeout.filter(|timeout| !(timeout.is_finite() && *timeout > 0.0)) {
+                problems.push(format!("{} must be a positive number of seconds (got {})", name, timeout));
+            }
+        }
+        if !(self.render.fan_card_angle > 0.0 && self.render.fan_card_angle < 90.0) {
+            problems.push(format!("render.fan_card_angle must be between 0 and 90 degrees (got {})", self.render.fan_card_angle));
+        }
+        if !(self.render.fan_circle_center_distance.is_finite() && self.render.fan_circle_center_distance > 0.0) {
+            problems.push(format!("render.fan_circle_center_distance must be a positive number (got {})", self.render.fan_circle_center_distance));
+        }
+
+        if !self.art.base.contains("{id}") {
+            problems.push(format!("art.base \"{}\" must contain {{id}} placeholder", self.art.base));
+        }
+        for rule in &self.art.variants {
+            if rule.min == 0 || rule.min > rule.max {
+                problems.push(format!("art.variants rule {}-{} has invalid range (variant 0 is base art)", rule.min, rule.max));
+            }
+            if !rule.path.contains("{id}") {
+                problems.push(format!("art.variants rule {}-{} path \"{}\" must contain {{id}} placeholder", rule.min, rule.max, rule.path));
+            }
+        }
+        if self.art.fallback.contains(&ArtFallback::Placeholder) {
+            match &self.art.placeholder {
+                Some(placeholder) if !Path::new(&self.paths.character_images).join(placeholder).is_file() => {
+                    problems.push(format!("art.placeholder \"{}\" does not exist in character images directory", placeholder));
+                }
+                Some(_) => (),
+                None => problems.push("art.fallback uses placeholder but art.placeholder is not set".to_string()),
+            }
+        }
+
+        for (name, value) in [
+            ("render.queue_size", self.render.queue_size as u64),
+            ("limits.max_fan_cards", self.limits.max_fan_cards as u64),
+            ("limits.max_album_cards", self.limits.max_album_cards as u64),
+            ("limits.max_pixels", self.limits.max_pixels),
+            ("limits.max_request_size", self.limits.max_request_size as u64),
+        ] {
+            if value == 0 {
+                problems.push(format!("{} must be greater than 0", name));
+            }
+        }
+
+        // Goes straight into response headers
+        if !self.http.cache_control.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+            problems.push(format!("http.cache_control \"{}\" is not a valid header value", self.http.cache_control));
+        }
+
+        if self.storage.backend == StorageBackend::S3 {
+            for (name, value) in [
+                ("storage.endpoint", &self.storage.endpoint),
+                ("storage.bucket", &self.storage.bucket),
+                ("storage.access_key", &self.storage.access_key),
+                ("storage.secret_key", &self.storage.secret_key),
+            ] {
+                if value.is_none() {
+                    problems.push(format!("{} must be set when storage.backend is s3", name));
+                }
+            }
+            if let Some(endpoint) = self.storage.endpoint.as_ref().filter(|endpoint| !(endpoint.starts_with("http://") || endpoint.starts_with("https://"))) {
+                problems.push(format!("storage.endpoint \"{}\" must be http:// or https:// URL", endpoint));
+            }
+            if self.storage.region.is_empty() {
+                problems.push("storage.region must not be empty".to_string());
+            }
+        }
+
+        if self.auth.require_signatures && self.auth.secret.is_none() {
+            problems.push("auth.require_signatures is enabled but auth.secret is not set".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid configuration:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print secrets into logs
+        let mut redacted = self.clone();
+        for secret in [&mut redacted.auth.secret, &mut redacted.auth.admin_token, &mut redacted.storage.secret_key] {
+            if secret.is_some() {
+                *secret = Some("<redacted>".to_string());
+            }
+        }
+
+        match toml::to_string_pretty(&redacted) {
+            Ok(content) => f.write_str(content.trim_end()),
+            Err(_) => write!(f, "{:?}", redacted),
+        }
+    }
+}
+
+enum SetError {
+    UnknownOption,
+    InvalidValue(String),
+}
+
+impl From<String> for SetError {
+    fn from(message: String) -> Self {
+        SetError::InvalidValue(message)
+    }
+}
+
+impl fmt::Display for SetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            FrameType::MoonweaverFrame => "moonweaver".to_string(),
-            FrameType::EssentiaFrame => "essentia".to_string(),
-            // FrameType::BetaFrame => "beta".to_string(),
-            // FrameType::EdoHiganFrame => "edo-higan".to_string()
+            SetError::UnknownOption => f.write_str("unknown option"),
+            SetError::InvalidValue(message) => f.write_str(message),
         }
     }
 }
 
-pub struct FrameDetails {
-    pub name: &'static str,
-    pub static_model: bool,
-    pub color_model: bool,
-    pub extendable: bool,
-    pub width: u32,
-    pub height: u32
-}
\ No newline at end of file
+fn parse_value<T: FromStr>(value: &str) -> Result<T, String> {
+    value.trim().parse().map_err(|_| format!("\"{}\" is not a valid value", value))
+}
+
+/// Empty value unsets option.
+fn parse_optional<T: FromStr>(value: &str) -> Result<Option<T>, String> {
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    parse_value(value).map(Some)
+}
+
+/// Accepts both `--key value` and `--key=value` forms.
+fn parse_flags(args: impl Iterator<Item = String>) -> Result<Vec<(String, String)>, String> {
+    let mut args = args.peekable();
+    let mut flags = Vec::new();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            println!("{}", USAGE);
+            std::process::exit(0);
+        }
+
+        let Some(flag) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument \"{}\"\n\n{}", arg, USAGE));
+        };
+
+        match flag.split_once('=') {
+            Some((key, value)) => flags.push((key.to_string(), value.to_string())),
+            None => match args.next() {
+                Some(value) => flags.push((flag.to_string(), value)),
+                None => return Err(format!("missing value for --{} flag", flag)),
+            },
+        }
+    }
+
+    Ok(flags)
+}
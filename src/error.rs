@@ -0,0 +1,145 @@
+use std::fmt;
+use axum::{http::{header, HeaderValue, StatusCode}, response::{IntoResponse, Response}, Json};
+use serde::Serialize;
+
+/// Every way render request can fail. Each variant maps to its own HTTP status and machine readable code,
+/// so clients (like the bot) can tell users what actually went wrong.
+#[derive(Debug, Clone)]
+pub enum RenderError {
+    /// Hash in URL is not a valid render hash.
+    InvalidHash(String),
+    /// Request was decoded but does not follow API structure.
+    InvalidRequest(String),
+    /// Service requires signed requests but request has no signature.
+    MissingSignature(String),
+    /// Request signature (or its expiry) doesn't match request.
+    InvalidSignature(String),
+    /// Request was signed properly, but signature has expired.
+    ExpiredSignature(String),
+    /// Admin request without valid admin token.
+    Unauthorized(String),
+    /// Requested resource (e.g. saved render) doesn't exist.
+    NotFound(String),
+    /// Request (hash or body) is bigger than allowed.
+    TooLarge(String),
+    /// Request is well formed, but rendering it would exceed configured limits (card count, output size).
+    LimitExceeded(String),
+    /// Requested frame doesn't exist or can't be used in requested way (e.g. kindled).
+    InvalidFrame(String),
+    /// Requested art variant is not supported.
+    InvalidVariant(String),
+    /// Art needed for render is missing on disk.
+    AssetMissing(String),
+    /// Art needed for render exists but couldn't be decoded.
+    AssetDamaged(String),
+    /// Render took longer than allowed (value in seconds).
+    Timeout(f32),
+    /// Render was cancelled before finishing (client disconnected).
+    Cancelled,
+    /// Render queue is full (value is suggested retry delay in seconds).
+    Overloaded(u64),
+    /// Render succeeded but result couldn't be encoded.
+    EncodeFailed(String),
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl RenderError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RenderError::InvalidHash(_) | RenderError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            RenderError::MissingSignature(_) | RenderError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            RenderError::InvalidSignature(_) | RenderError::ExpiredSignature(_) => StatusCode::FORBIDDEN,
+            RenderError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            RenderError::LimitExceeded(_) | RenderError::InvalidFrame(_) | RenderError::InvalidVariant(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            RenderError::AssetMissing(_) | RenderError::NotFound(_) => StatusCode::NOT_FOUND,
+            RenderError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            RenderError::Overloaded(_) => StatusCode::SERVICE_UNAVAILABLE,
+            // Nobody is there to read it, but it shows up properly in access logs (nginx convention)
+            RenderError::Cancelled => StatusCode::from_u16(499).unwrap_or(StatusCode::BAD_REQUEST),
+            RenderError::AssetDamaged(_) | RenderError::EncodeFailed(_) | RenderError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            RenderError::InvalidHash(_) => "invalid_hash",
+            RenderError::InvalidRequest(_) => "invalid_request",
+            RenderError::MissingSignature(_) => "missing_signature",
+            RenderError::InvalidSignature(_) => "invalid_signature",
+            RenderError::ExpiredSignature(_) => "expired_signature",
+            RenderError::Unauthorized(_) => "unauthorized",
+            RenderError::NotFound(_) => "not_found",
+            RenderError::TooLarge(_) => "too_large",
+            RenderError::LimitExceeded(_) => "limit_exceeded",
+            RenderError::InvalidFrame(_) => "invalid_frame",
+            RenderError::InvalidVariant(_) => "invalid_variant",
+            RenderError::AssetMissing(_) => "asset_missing",
+            RenderError::AssetDamaged(_) => "asset_damaged",
+            RenderError::Timeout(_) => "timeout",
+            RenderError::Overloaded(_) => "overloaded",
+            RenderError::Cancelled => "cancelled",
+            RenderError::EncodeFailed(_) => "encode_failed",
+            RenderError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::InvalidHash(message)
+            | RenderError::InvalidRequest(message)
+            | RenderError::MissingSignature(message)
+            | RenderError::InvalidSignature(message)
+            | RenderError::ExpiredSignature(message)
+            | RenderError::Unauthorized(message)
+            | RenderError::NotFound(message)
+            | RenderError::TooLarge(message)
+            | RenderError::LimitExceeded(message)
+            | RenderError::InvalidFrame(message)
+            | RenderError::InvalidVariant(message)
+            | RenderError::AssetMissing(message)
+            | RenderError::AssetDamaged(message)
+            | RenderError::EncodeFailed(message)
+            | RenderError::Internal(message) => f.write_str(message),
+            RenderError::Timeout(seconds) => write!(f, "asset render took more than {} seconds", seconds),
+            RenderError::Cancelled => f.write_str("render was cancelled"),
+            RenderError::Overloaded(seconds) => write!(f, "render queue is full, retry in {} seconds", seconds),
+        }
+    }
+}
+
+impl IntoResponse for RenderError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody { code: self.code(), message: self.to_string() };
+        let mut response = (self.status(), Json(body)).into_response();
+        if let RenderError::Overloaded(seconds) = self {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+/// Rejects empty layouts and layouts with more cards than allowed.
+pub fn check_card_count(count: usize, max: usize, kind: &str) -> Result<(), RenderError> {
+    if count == 0 {
+        return Err(RenderError::InvalidRequest(format!("{} must contain at least one card", kind)));
+    }
+    if count > max {
+        return Err(RenderError::LimitExceeded(format!("{} has {} cards, at most {} are allowed", kind, count, max)));
+    }
+    Ok(())
+}
+
+pub fn check_canvas_size(width: u32, height: u32, max_pixels: u64) -> Result<(), RenderError> {
+    if width as u64 * height as u64 > max_pixels {
+        return Err(RenderError::LimitExceeded(format!("render output would be {}x{} pixels, at most {} pixels are allowed", width, height, max_pixels)));
+    }
+    Ok(())
+}
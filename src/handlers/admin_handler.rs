@@ -0,0 +1,112 @@
+use axum::{extract::{FromRequestParts, Path, Query, State}, http::{header, request::Parts}, routing::{get, post}, Json, Router};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::error::RenderError;
+use crate::state::AppState;
+use crate::utils::render_store::{self, SavedRender};
+use crate::utils::{signing, RetentionReport};
+
+/// Saved render management, every route requires `Authorization: Bearer <auth.admin_token>` header.
+pub fn admin_routes() -> Router<AppState> {
+    Router::new()
+        .route("/renders", get(handle_list_renders).delete(handle_delete_renders))
+        .route("/renders/{name}", get(handle_render_info).delete(handle_delete_render))
+        .route("/retention", post(handle_run_retention))
+}
+
+/// Extracting it rejects requests without valid admin token.
+pub struct AdminAuth;
+
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = RenderError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(admin_token) = &state.config.auth.admin_token else {
+            return Err(RenderError::Unauthorized("admin API is disabled".to_string()));
+        };
+
+        let provided = parts.headers.get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match provided {
+            Some(provided) if signing::tokens_match(admin_token, provided.trim()) => Ok(AdminAuth),
+            Some(_) => Err(RenderError::Unauthorized("admin token is invalid".to_string())),
+            None => Err(RenderError::Unauthorized("admin token is missing".to_string())),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PatternQuery {
+    pattern: Option<String>, // glob with `*` and `?`
+}
+
+#[derive(Serialize)]
+pub struct DeleteResponse {
+    deleted: Vec<String>,
+}
+
+/// `GET /admin/renders[?pattern=<glob>]`
+pub async fn handle_list_renders(_: AdminAuth, State(state): State<AppState>, Query(query): Query<PatternQuery>) -> Result<Json<Vec<SavedRender>>, RenderError> {
+    let pattern = query.pattern.as_deref().unwrap_or("*");
+    render_store::list_saved(state.render_storage.as_ref(), pattern, true).await
+        .map(Json)
+        .map_err(|e| io_error("list saved renders", e))
+}
+
+/// `GET /admin/renders/{name}`
+pub async fn handle_render_info(_: AdminAuth, State(state): State<AppState>, Path(name): Path<String>) -> Result<Json<SavedRender>, RenderError> {
+    render_store::validate_save_name(&name)?;
+    match render_store::saved_info(state.render_storage.as_ref(), &name).await {
+        Ok(Some(render)) => Ok(Json(render)),
+        Ok(None) => Err(RenderError::NotFound(format!("saved render {} doesn't exist", name))),
+        Err(e) => Err(io_error("read saved render", e)),
+    }
+}
+
+/// `DELETE /admin/renders/{name}`
+pub async fn handle_delete_render(_: AdminAuth, State(state): State<AppState>, Path(name): Path<String>) -> Result<Json<DeleteResponse>, RenderError> {
+    render_store::validate_save_name(&name)?;
+    match render_store::delete_saved(state.render_storage.as_ref(), &name).await {
+        Ok(true) => {
+            info!("Deleted saved render {} (admin API)", name);
+            Ok(Json(DeleteResponse { deleted: vec![name] }))
+        }
+        Ok(false) => Err(RenderError::NotFound(format!("saved render {} doesn't exist", name))),
+        Err(e) => Err(io_error("delete saved render", e)),
+    }
+}
+
+/// `DELETE /admin/renders?pattern=<glob>` - pattern is required, so everything can't be deleted by accident
+/// (`pattern=*` has to be asked for explicitly).
+pub async fn handle_delete_renders(_: AdminAuth, State(state): State<AppState>, Query(query): Query<PatternQuery>) -> Result<Json<DeleteResponse>, RenderError> {
+    let Some(pattern) = query.pattern else {
+        return Err(RenderError::InvalidRequest("pattern of renders to delete is required".to_string()));
+    };
+
+    let renders = render_store::list_saved(state.render_storage.as_ref(), &pattern, false).await.map_err(|e| io_error("list saved renders", e))?;
+    let mut deleted = Vec::with_capacity(renders.len());
+    for render in renders {
+        match render_store::delete_saved(state.render_storage.as_ref(), &render.name).await {
+            Ok(true) => deleted.push(render.name),
+            Ok(false) => (),
+            Err(e) => error!("Failed to delete saved render {}. Received error: {}", render.name, e),
+        }
+    }
+
+    info!("Deleted {} saved render(s) matching {} (admin API)", deleted.len(), pattern);
+    Ok(Json(DeleteResponse { deleted }))
+}
+
+/// `POST /admin/retention` - enforces retention of saved renders right away, reports what was evicted.
+pub async fn handle_run_retention(_: AdminAuth, State(state): State<AppState>) -> Result<Json<RetentionReport>, RenderError> {
+    state.render_retention.run().await
+        .map(Json)
+        .map_err(|e| io_error("enforce retention of saved renders", e))
+}
+
+fn io_error(action: &str, e: std::io::Error) -> RenderError {
+    error!("Failed to {}. Received error: {}", action, e);
+    RenderError::Internal(format!("failed to {}", action))
+}
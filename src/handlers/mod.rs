@@ -1 +1,5 @@
+mod admin_handler;
 pub mod render;
+mod stats_handler;
+pub use admin_handler::admin_routes;
+pub use stats_handler::handle_stats_request;
@@ -0,0 +1,105 @@
+use image::DynamicImage;
+
+use crate::config::RenderConfig;
+use crate::error::RenderError;
+use crate::handlers::render::pipeline::RenderKind;
+use crate::models::{CardRenderRequestData, FanRenderRequestData};
+use crate::utils::{card_size, render_album, render_card, render_fan, validate_album, validate_fan, Deadline, RenderContext};
+
+pub struct Card;
+pub struct Fan;
+pub struct Album;
+
+impl RenderKind for Card {
+    type Request = CardRenderRequestData;
+    const NAME: &'static str = "card";
+
+    fn save_name(request: &Self::Request) -> Option<&str> {
+        request.save_name.as_deref()
+    }
+
+    fn clear_save_names(request: &mut Self::Request) {
+        request.save_name = None;
+    }
+
+    fn cards(request: &Self::Request) -> Vec<&CardRenderRequestData> {
+        vec![request]
+    }
+
+    fn validate(request: &Self::Request, ctx: &RenderContext) -> Result<(), RenderError> {
+        card_size(request, ctx).map(|_| ())
+    }
+
+    fn timeout(config: &RenderConfig) -> f32 {
+        config.card_timeout.unwrap_or(config.timeout)
+    }
+
+    fn render(request: Self::Request, ctx: &RenderContext, deadline: &Deadline) -> Result<DynamicImage, RenderError> {
+        render_card(&request, ctx, deadline)
+    }
+}
+
+impl RenderKind for Fan {
+    type Request = FanRenderRequestData;
+    const NAME: &'static str = "fan";
+
+    fn save_name(request: &Self::Request) -> Option<&str> {
+        request.save_name.as_deref()
+    }
+
+    fn clear_save_names(request: &mut Self::Request) {
+        clear_save_names(request);
+    }
+
+    fn cards(request: &Self::Request) -> Vec<&CardRenderRequestData> {
+        request.cards.iter().collect()
+    }
+
+    fn validate(request: &Self::Request, ctx: &RenderContext) -> Result<(), RenderError> {
+        validate_fan(&request.cards, ctx)
+    }
+
+    fn timeout(config: &RenderConfig) -> f32 {
+        config.fan_timeout.unwrap_or(config.timeout)
+    }
+
+    fn render(request: Self::Request, ctx: &RenderContext, deadline: &Deadline) -> Result<DynamicImage, RenderError> {
+        render_fan(request.cards, ctx, deadline)
+    }
+}
+
+impl RenderKind for Album {
+    type Request = FanRenderRequestData;
+    const NAME: &'static str = "album";
+
+    fn save_name(request: &Self::Request) -> Option<&str> {
+        request.save_name.as_deref()
+    }
+
+    fn clear_save_names(request: &mut Self::Request) {
+        clear_save_names(request);
+    }
+
+    fn cards(request: &Self::Request) -> Vec<&CardRenderRequestData> {
+        request.cards.iter().collect()
+    }
+
+    fn validate(request: &Self::Request, ctx: &RenderContext) -> Result<(), RenderError> {
+        validate_album(&request.cards, ctx)
+    }
+
+    fn timeout(config: &RenderConfig) -> f32 {
+        config.album_timeout.unwrap_or(config.timeout)
+    }
+
+    fn render(request: Self::Request, ctx: &RenderContext, deadline: &Deadline) -> Result<DynamicImage, RenderError> {
+        render_album(request.cards, ctx, deadline)
+    }
+}
+
+fn clear_save_names(request: &mut FanRenderRequestData) {
+    request.save_name = None;
+    for card in &mut request.cards {
+        card.save_name = None;
+    }
+}
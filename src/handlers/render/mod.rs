@@ -1,6 +1,4 @@
-mod album_handler;
-mod card_handler;
-mod fan_handler;
-pub use album_handler::handle_card_album_request;
-pub use card_handler::handle_card_request;
-pub use fan_handler::handle_card_fan_request;
\ No newline at end of file
+mod kinds;
+mod pipeline;
+pub use kinds::{Album, Card, Fan};
+pub use pipeline::render_routes;
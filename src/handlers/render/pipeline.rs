@@ -0,0 +1,277 @@
+use axum::{body::{Body, Bytes}, extract::{Path, State}, http::{header, response::Builder, HeaderMap, StatusCode}, response::{IntoResponse, Response}, routing::{get, post}, Router};
+use image::DynamicImage;
+use serde::{de::DeserializeOwned, Serialize};
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::Instant;
+use log::{warn, error};
+
+use crate::config::RenderConfig;
+use crate::models::CardRenderRequestData;
+use crate::error::RenderError;
+use crate::state::AppState;
+use crate::utils::render_store::RenderMetadata;
+use crate::utils::signing::Verification;
+use crate::utils::{decode_hash, encode_hash, render_fingerprint, render_store, signing, Deadline, HashError, RenderContext};
+
+/// Single kind of render (card, fan, album, ...). Everything else - decoding, disk cache, encoding and
+/// saving - is shared by render pipeline, so adding new layout means implementing this trait and
+/// registering it with [`render_routes`].
+pub trait RenderKind: 'static {
+    type Request: Serialize + DeserializeOwned + Clone + Send + 'static;
+
+    /// Used in route path (`/render/{NAME}`) and messages.
+    const NAME: &'static str;
+
+    fn save_name(request: &Self::Request) -> Option<&str>;
+
+    /// Clears every `save_name` in request (including ones of its cards), they don't affect render output.
+    fn clear_save_names(request: &mut Self::Request);
+
+    /// Cheap checks (frames exist, card count, output size) done before anything else, so requests over budget never
+    /// get to fingerprinting, cache lookups or render pool.
+    fn validate(request: &Self::Request, ctx: &RenderContext) -> Result<(), RenderError>;
+
+    /// Cards request consists of, used to fingerprint assets its render depends on.
+    fn cards(request: &Self::Request) -> Vec<&CardRenderRequestData>;
+
+    /// Time limit (in seconds) of single render of this kind.
+    fn timeout(config: &RenderConfig) -> f32;
+
+    fn render(request: Self::Request, ctx: &RenderContext, deadline: &Deadline) -> Result<DynamicImage, RenderError>;
+}
+
+/// `GET /{NAME}/{hash}` and `POST /{NAME}` routes of given render kind.
+pub fn render_routes<K: RenderKind>() -> Router<AppState> {
+    Router::new()
+        .route(&format!("/{}", K::NAME), post(handle_json_request::<K>))
+        .route(&format!("/{}/{{hash}}", K::NAME), get(handle_hash_request::<K>))
+}
+
+async fn handle_hash_request<K: RenderKind>(Path(hash): Path<String>, State(state): State<AppState>, headers: HeaderMap) -> Response<Body> {
+    let deadline = Deadline::new(Instant::now(), K::timeout(&state.config.render));
+    let auth = &state.config.auth;
+
+    let (hash, expires, signature) = signing::split_signed_hash(&hash);
+    let verification = match signing::verify(auth.secret.as_deref(), auth.require_signatures, hash.as_bytes(), expires, signature) {
+        Ok(verification) => verification,
+        Err(e) => return e.into_response(),
+    };
+
+    let decoded: K::Request = match decode_hash(hash, state.config.limits.max_request_size) {
+        Ok(decoded) => decoded,
+        Err(HashError::Malformed) => return RenderError::InvalidHash(format!("provided {} hash is invalid", K::NAME)).into_response(),
+        Err(HashError::InvalidStructure) => return RenderError::InvalidRequest(format!("provided {} hash is valid but does not follow API structure", K::NAME)).into_response(),
+        Err(HashError::TooLarge) => return RenderError::TooLarge(format!("provided {} hash exceeds {} bytes", K::NAME, state.config.limits.max_request_size)).into_response(),
+    };
+
+    render_response::<K>(decoded, state, deadline, &headers, verification).await
+}
+
+/// POST body is signed as a whole, signature (and optional expiry) is passed in `X-Signature` (`X-Signature-Expires`) header.
+async fn handle_json_request<K: RenderKind>(State(state): State<AppState>, headers: HeaderMap, body: Bytes) -> Response<Body> {
+    let deadline = Deadline::new(Instant::now(), K::timeout(&state.config.render));
+    let auth = &state.config.auth;
+
+    if body.len() > state.config.limits.max_request_size {
+        return RenderError::TooLarge(format!("provided {} json exceeds {} bytes", K::NAME, state.config.limits.max_request_size)).into_response();
+    }
+
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let verification = match signing::verify(auth.secret.as_deref(), auth.require_signatures, &body, header("X-Signature-Expires"), header("X-Signature")) {
+        Ok(verification) => verification,
+        Err(e) => return e.into_response(),
+    };
+
+    let decoded: K::Request = match serde_json::from_slice(&body) {
+        Ok(decoded) => decoded,
+        Err(e) => return RenderError::InvalidRequest(format!("provided {} json does not follow API structure: {}", K::NAME, e)).into_response(),
+    };
+
+    render_response::<K>(decoded, state, deadline, &headers, verification).await
+}
+
+/// Shared by GET (hash) and POST (json) routes. Every response carries canonical GET hash of the request (signed with
+/// the same expiry as request when request itself was signed, so it's accepted even when signatures are required),
+/// renders also carry strong ETag (render fingerprint), so clients and proxies can revalidate them.
+async fn render_response<K: RenderKind>(decoded: K::Request, state: AppState, deadline: Deadline, headers: &HeaderMap, verification: Verification) -> Response<Body> {
+    let hash = encode_hash(&decoded);
+    // Service never signs anything that wasn't signed already, so unsigned requests can't be turned into signed URLs
+    let response_hash = match (state.config.auth.secret.as_deref(), verification) {
+        (Some(secret), Verification::Signed { expires }) => signing::sign_hash(secret, &hash, expires),
+        _ => hash.clone(),
+    };
+    let save_name = K::save_name(&decoded).map(str::to_string);
+    if let Some(Err(e)) = save_name.as_deref().map(render_store::validate_save_name) {
+        return e.into_response();
+    }
+    let ctx = state.render_context();
+    if let Err(e) = K::validate(&decoded, &ctx) {
+        return e.into_response();
+    }
+
+    // `save_name` is only an alias, so requests differing just in it share fingerprint (and cache entries)
+    let mut unnamed = decoded.clone();
+    K::clear_save_names(&mut unnamed);
+    let fingerprint_hash = encode_hash(&unnamed);
+
+    // Fingerprint checks versions of art files on disk
+    let fingerprint_ctx = ctx.clone();
+    let (decoded, fingerprint) = match tokio::task::spawn_blocking(move || {
+        let fingerprint = render_fingerprint(K::NAME, &fingerprint_hash, &K::cards(&decoded), &fingerprint_ctx);
+        (decoded, fingerprint)
+    }).await {
+        Ok(result) => result,
+        Err(e) => return RenderError::Internal(format!("failed to fingerprint {} request: {}", K::NAME, e)).into_response(),
+    };
+    let etag = format!("\"{}\"", fingerprint);
+
+    let if_none_match = headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok());
+    if if_none_match.is_some_and(|tags| etag_matches(tags, &etag)) {
+        return with_cache_headers(Response::builder().status(StatusCode::NOT_MODIFIED), &state, &response_hash, Some(&etag))
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    if let Err(e) = deadline.check() {
+        return e.into_response();
+    }
+
+    // Stored next to saved render, so it can be told later what it was rendered from
+    let metadata = save_name.as_ref().map(|_| RenderMetadata {
+        kind: K::NAME.to_string(),
+        hash: hash.clone(),
+        fingerprint: fingerprint.clone(),
+        spec: serde_json::to_value(&decoded).unwrap_or_default(),
+        digest: String::new(), // filled in once render is saved
+    });
+
+    if let Some(png) = state.response_cache.get(&fingerprint) {
+        if let (Some(save_name), Some(metadata)) = (&save_name, &metadata) {
+            // Entry is usually in disk cache too, when it isn't render is written as a copy
+            let cached_path = state.disk_cache.as_ref().map(|disk_cache| disk_cache.entry_path(&fingerprint));
+            save_render(&state, save_name, &png, metadata, cached_path).await;
+        }
+        return png_response("loaded from memory cache", png, &state, &response_hash, Some(&etag), &deadline);
+    }
+
+    if let Some(disk_cache) = &state.disk_cache {
+        if let Some(png) = disk_cache.get(&fingerprint).await {
+            state.response_cache.insert(fingerprint.clone(), png.clone());
+            if let (Some(save_name), Some(metadata)) = (&save_name, &metadata) {
+                save_render(&state, save_name, &png, metadata, Some(disk_cache.entry_path(&fingerprint))).await;
+            }
+            return png_response("loaded from disk cache", png, &state, &response_hash, Some(&etag), &deadline);
+        }
+    }
+
+    if let Some(save_name) = &save_name {
+        // Only served when it was rendered from this very request and current assets
+        if let Some(png) = render_store::read_saved(state.render_storage.as_ref(), save_name, K::NAME, &fingerprint).await {
+            return png_response("loaded from disk cache", png, &state, &response_hash, Some(&etag), &deadline);
+        }
+    }
+
+    // Identical requests coming in while this one renders wait for it instead of rendering it again. Render carries on
+    // as long as any of them waits, it's only cancelled once all of their clients disconnect. Fingerprint covers render
+    // kind, so fan and album made of the same cards never share render.
+    let flight_state = state.clone();
+    let flight_hash = hash.clone();
+    let flight_fingerprint = fingerprint.clone();
+    let flight_deadline = deadline.clone();
+    let flight = state.render_flights.run(fingerprint.clone(), move || async move {
+        let (state, hash, fingerprint, deadline) = (flight_state, flight_hash, flight_fingerprint, flight_deadline);
+        // Rendering and encoding are CPU heavy, so they run on dedicated render pool rather than on async runtime
+        let job_hash = hash.clone();
+        let job_deadline = deadline.clone();
+        let cancel_guard = deadline.cancel_on_drop();
+        let result = state.render_pool.run(move || render_png::<K>(decoded, &ctx, &job_deadline, &job_hash)).await;
+        cancel_guard.disarm();
+
+        let inner = Bytes::from(result??);
+        deadline.check()?;
+
+        state.response_cache.insert(fingerprint.clone(), inner.clone());
+
+        // Render itself succeeded, so failed write only costs re-render next time
+        if let Some(disk_cache) = &state.disk_cache {
+            match disk_cache.insert(&fingerprint, &inner).await {
+                Ok(()) => state.render_retention.record_write(inner.len() as u64),
+                Err(e) => warn!("Failed to write freshly rendered {} asset into disk cache. Received error: {} (request hash = {})", K::NAME, e, hash),
+            }
+        }
+
+        Ok(inner)
+    }).await;
+
+    let inner = match flight {
+        Some(Ok(inner)) => inner,
+        Some(Err(e)) => return e.into_response(),
+        None => return RenderError::Internal(format!("{} render task failed", K::NAME)).into_response(),
+    };
+
+    // Requests sharing render can differ in save name, so each of them saves its own
+    if let (Some(save_name), Some(metadata)) = (&save_name, &metadata) {
+        let cached_path = state.disk_cache.as_ref().map(|disk_cache| disk_cache.entry_path(&fingerprint));
+        save_render(&state, save_name, &inner, metadata, cached_path).await;
+    }
+
+    png_response("rendered on request", inner, &state, &response_hash, Some(&etag), &deadline)
+}
+
+/// `save_name` is only an alias of render, so it's written (again) whenever it doesn't hold render of this request yet.
+/// When render is in disk cache (`cached_path`), alias is hard linked to it instead of taking extra space.
+async fn save_render(state: &AppState, save_name: &str, png: &Bytes, metadata: &RenderMetadata, cached_path: Option<PathBuf>) {
+    let storage = state.render_storage.as_ref();
+    if render_store::is_saved(storage, save_name, &metadata.kind, &metadata.fingerprint, png).await {
+        // Counts as use of saved render too, so retention doesn't evict it as unused
+        storage.touch(save_name);
+        return;
+    }
+
+    match render_store::write_saved(storage, save_name, png.clone(), metadata, cached_path.as_deref()).await {
+        Ok(()) => state.render_retention.record_write(png.len() as u64),
+        Err(e) => warn!("Failed to save {} asset as {}. Received error: {} (request hash = {})", metadata.kind, save_name, e, metadata.hash),
+    }
+}
+
+fn png_response(source: &str, png: Bytes, state: &AppState, hash: &str, etag: Option<&str>, deadline: &Deadline) -> Response<Body> {
+    with_cache_headers(Response::builder(), state, hash, etag)
+        .header("X-Source", source)
+        .header("X-Processing-Time", (deadline.elapsed().as_nanos() as f64 / 1_000_000.0).to_string() + "ms")
+        .header("Content-Type", "image/png")
+        .body(Body::from(png))
+        .unwrap()
+}
+
+fn with_cache_headers(mut builder: Builder, state: &AppState, hash: &str, etag: Option<&str>) -> Builder {
+    builder = builder.header("X-Render-Hash", hash);
+    if let Some(etag) = etag {
+        builder = builder.header(header::ETAG, etag);
+    }
+    if !state.config.http.cache_control.is_empty() {
+        builder = builder.header(header::CACHE_CONTROL, &state.config.http.cache_control);
+    }
+    builder
+}
+
+/// `If-None-Match` can list multiple (possibly weak) tags or `*`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn render_png<K: RenderKind>(decoded: K::Request, ctx: &RenderContext, deadline: &Deadline, hash: &str) -> Result<Vec<u8>, RenderError> {
+    // Job could have waited in queue long enough for client to give up
+    deadline.check()?;
+    let image = K::render(decoded, ctx, deadline)?;
+    deadline.check()?;
+
+    let mut buffer = Cursor::new(Vec::new());
+    if let Err(e) = image.write_to(&mut buffer, image::ImageFormat::Png) {
+        error!("Properly rendered {} asset but failed to write it into final buffer. Received error: {} (hash request = {})", K::NAME, e, hash);
+        return Err(RenderError::EncodeFailed(format!("cannot write {} image to buffer", K::NAME)));
+    }
+    Ok(buffer.into_inner())
+}
@@ -0,0 +1,27 @@
+use axum::{extract::State, Json};
+use serde::Serialize;
+
+use crate::state::AppState;
+use crate::utils::{CacheStats, DiskCacheStats, FlightStats, PoolStats};
+
+#[derive(Serialize)]
+pub struct StatsResponse {
+    art_cache: CacheStats,
+    mask_cache: CacheStats,
+    response_cache: CacheStats,
+    disk_cache: Option<DiskCacheStats>, // missing when disk cache is disabled
+    render_pool: PoolStats,
+    render_flights: FlightStats,
+}
+
+#[axum_macros::debug_handler]
+pub async fn handle_stats_request(State(state): State<AppState>) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        art_cache: state.art_cache.stats(),
+        mask_cache: state.mask_cache.stats(),
+        response_cache: state.response_cache.stats(),
+        disk_cache: state.disk_cache.as_ref().map(|disk_cache| disk_cache.stats()),
+        render_pool: state.render_pool.stats(),
+        render_flights: state.render_flights.stats(),
+    })
+}
@@ -1,42 +1,102 @@
 mod config;
+mod error;
 mod handlers;
 mod models;
+mod state;
 mod utils;
 
-use axum::{body::Body, http::Response, routing::get, serve, Extension, Router};
+use axum::{body::Body, http::Response, routing::get, serve, Router};
 use log::{error, info};
 use pretty_env_logger::init as init_logger;
 use std::sync::Arc;
 use tokio::net::TcpListener;
 use tokio::signal;
 
-use crate::handlers::render::{
-    handle_card_album_request, handle_card_fan_request, handle_card_request,
-};
+use crate::config::Config;
+use crate::handlers::{admin_routes, handle_stats_request};
+use crate::handlers::render::{render_routes, Album, Card, Fan};
+use crate::state::AppState;
+use crate::utils::{ArtCache, DiskCache, FrameStore, MaskCache, RenderPool, RenderRetention, ResponseCache, SingleFlight};
 
 #[tokio::main]
 async fn main() {
     init_logger();
+
+    if std::env::args().nth(1).as_deref() == Some("sign") {
+        sign_command();
+        return;
+    }
+
     info!("Starting service...");
 
-    let frames = Arc::new(utils::load_frames());
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    info!("Loaded configuration:\n{}", config);
+
+    let frames = match utils::load_frames(&config.paths.frames, config.frames.strict) {
+        Ok(frames) => Arc::new(FrameStore::new(frames)),
+        Err(e) => {
+            error!("Failed to load frames: {}", e);
+            std::process::exit(1);
+        }
+    };
+    utils::spawn_frame_reloader(frames.clone(), config.paths.frames.clone(), config.frames.watch_interval, config.frames.strict);
+
+    let render_pool = match RenderPool::new(config.render.threads, config.render.queue_size, config.render.retry_after) {
+        Ok(render_pool) => Arc::new(render_pool),
+        Err(e) => {
+            error!("Failed to start render pool: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let disk_cache = config.paths.cache.as_ref().map(|path| Arc::new(DiskCache::new(path)));
+    let render_storage = match utils::open_storage(&config) {
+        Ok(render_storage) => render_storage,
+        Err(e) => {
+            error!("Failed to set up render storage: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let render_retention = Arc::new(RenderRetention::new(config.retention.clone(), render_storage.clone(), disk_cache.clone()));
+    utils::spawn_render_retention(render_retention.clone());
+
+    let state = AppState {
+        art_cache: Arc::new(ArtCache::new(config.cache.art_memory * 1024 * 1024)),
+        mask_cache: Arc::new(MaskCache::new(config.cache.mask_memory * 1024 * 1024)),
+        response_cache: Arc::new(ResponseCache::new(config.cache.response_memory * 1024 * 1024)),
+        disk_cache,
+        config: Arc::new(config),
+        frames,
+        render_pool,
+        render_storage,
+        render_retention,
+        render_flights: Arc::new(SingleFlight::new()),
+    };
 
     let router = Router::new()
         .nest(
             "/render",
             Router::new()
-                .route("/card/{hash}", get(handle_card_request))
-                .route("/fan/{hash}", get(handle_card_fan_request))
-                .route("/album/{hash}", get(handle_card_album_request)), //.route("/{file_name}", delete(handlers::render_remove))
+                .merge(render_routes::<Card>())
+                .merge(render_routes::<Fan>())
+                .merge(render_routes::<Album>()),
         )
+        .nest("/admin", admin_routes())
+        .route("/stats", get(handle_stats_request))
         .fallback(|| async { Response::builder().status(418).body(Body::empty()).unwrap() })
-        .layer(Extension(frames));
+        .with_state(state.clone());
 
-    match TcpListener::bind(config::ADDRESS).await {
+    match TcpListener::bind(&state.config.address).await {
         Ok(listener) => {
             info!(
                 "Starting http server at {}. Server is ready.",
-                config::ADDRESS
+                state.config.address
             );
             if let Err(e) = serve(listener, router).with_graceful_shutdown(shutdown_signal()).await {
                 error!("Server encountered an error: {}", e);
@@ -44,7 +104,7 @@ async fn main() {
             }
         }
         Err(err) => {
-            error!("Failed to bind to {} tcp socket: {}", config::ADDRESS, err);
+            error!("Failed to bind to {} tcp socket: {}", state.config.address, err);
             std::process::exit(1);
         }
     }
@@ -52,6 +112,26 @@ async fn main() {
     info!("Successfully closed http server. Bye!")
 }
 
+/// `hagaki sign <hash> [expires]` - signs render hash with secret from `HAGAKI_AUTH_SECRET` env var
+/// (expires is unix timestamp in seconds).
+fn sign_command() {
+    let args: Vec<String> = std::env::args().skip(2).collect();
+    let (Some(hash), Ok(secret)) = (args.first(), std::env::var("HAGAKI_AUTH_SECRET")) else {
+        eprintln!("Usage: HAGAKI_AUTH_SECRET=<secret> hagaki sign <hash> [expires]");
+        std::process::exit(1);
+    };
+    let expires = match args.get(1).map(|expires| expires.parse::<u64>()) {
+        Some(Ok(expires)) => Some(expires),
+        Some(Err(_)) => {
+            eprintln!("Expiry must be unix timestamp in seconds.");
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    println!("{}", utils::signing::sign_hash(&secret, hash, expires));
+}
+
 // https://github.com/tokio-rs/axum/blob/main/examples/graceful-shutdown/src/main.rs
 async fn shutdown_signal() {
     let ctrl_c = async {
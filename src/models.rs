@@ -1,21 +1,56 @@
+use std::fmt;
 use serde::{Deserialize, Serialize};
 
-use crate::config::FrameType;
+/// Numeric frame identifier, matches `id` declared in frame manifest.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct FrameId(pub u8);
 
+impl fmt::Display for FrameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+// Compact render hashes encode fields by position, so new fields must be appended at the end and marked `#[serde(default)]`.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CardRenderRequestData {
     pub id: u32,
     pub variant: u8,
     pub dye: u32,
     pub kindled: bool,
-    pub frame_type: FrameType,
+    pub frame_type: FrameId,
+    #[serde(default)]
     pub offset_x: Option<i32>,
+    #[serde(default)]
     pub offset_y: Option<i32>,
-    pub save_name: Option<String> // Jeśli podane to znaczy zapisz plik na dysku, dokładnie pod podaną nazwą.png
+    #[serde(default)]
+    pub placement: Option<Placement>, // how character art is fitted into frame, defaults to `none`
+    #[serde(default)]
+    pub custom_art: Option<u32>, // when set, art is taken from custom card art directory instead of character id/variant
+    #[serde(default)]
+    pub save_name: Option<String>, // Jeśli podane to znaczy zapisz plik na dysku, dokładnie pod podaną nazwą.png
+}
+
+/// Controls how character art is resampled to frame size before being placed on canvas.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Placement {
+    /// Scale down/up (keeping aspect ratio) so whole art fits inside frame, centered.
+    Fit,
+    /// Scale (keeping aspect ratio) so art covers whole frame, cropping overflow, centered.
+    #[serde(alias = "cover")]
+    Fill,
+    /// Resize to exact frame size, ignoring aspect ratio.
+    Stretch,
+    /// Place art as is at top-left corner, anything outside frame is cut off.
+    #[default]
+    None,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FanRenderRequestData {
     pub cards: Vec<CardRenderRequestData>,
+    #[serde(default)]
     pub save_name: Option<String>
 }
\ No newline at end of file
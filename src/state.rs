@@ -0,0 +1,31 @@
+use std::sync::Arc;
+use axum::body::Bytes;
+
+use crate::config::Config;
+use crate::error::RenderError;
+use crate::utils::{ArtCache, DiskCache, FrameStore, MaskCache, RenderContext, RenderPool, RenderRetention, RenderStorage, ResponseCache, SingleFlight};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<Config>,
+    pub frames: Arc<FrameStore>,
+    pub art_cache: Arc<ArtCache>,
+    pub mask_cache: Arc<MaskCache>,
+    pub response_cache: Arc<ResponseCache>,
+    pub disk_cache: Option<Arc<DiskCache>>,
+    pub render_pool: Arc<RenderPool>,
+    pub render_storage: Arc<dyn RenderStorage>, // saved renders
+    pub render_retention: Arc<RenderRetention>,
+    pub render_flights: Arc<SingleFlight<Result<Bytes, RenderError>>>, // in progress renders by render fingerprint
+}
+
+impl AppState {
+    pub fn render_context(&self) -> RenderContext {
+        RenderContext {
+            config: self.config.clone(),
+            frames: self.frames.current(),
+            art_cache: self.art_cache.clone(),
+            mask_cache: self.mask_cache.clone(),
+        }
+    }
+}
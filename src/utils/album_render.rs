@@ -1,20 +1,31 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
-
 use image::{imageops::overlay, DynamicImage, ImageBuffer};
 
-use crate::config::{ALBUM_CARD_PADDING, RENDER_TIMEOUT};
+use crate::error::{check_canvas_size, check_card_count, RenderError};
 use crate::models::CardRenderRequestData;
-use crate::utils::render_card;
+use crate::utils::{card_size, render_card, Deadline, RenderContext};
 
 use rayon::prelude::*;
 
-pub fn render_album(data: Vec<CardRenderRequestData>, frames: &Arc<HashMap<String, DynamicImage>>, start_time: &Instant) -> Result<DynamicImage, String> {
+/// Card count and output size of album are known up front from frame sizes, so oversized albums are rejected before
+/// any card is rendered (and before request takes place in render pool).
+pub fn validate_album(data: &[CardRenderRequestData], ctx: &RenderContext) -> Result<(), RenderError> {
+    check_card_count(data.len(), ctx.config.limits.max_album_cards, "album")?;
+    let card_sizes = data.iter().map(|card| card_size(card, ctx)).collect::<Result<Vec<_>, _>>()?;
+    let (width, height, _) = album_layout(&card_sizes, ctx.config.render.album_card_padding);
+    check_canvas_size(width, height, ctx.config.limits.max_pixels)
+}
+
+pub fn render_album(data: Vec<CardRenderRequestData>, ctx: &RenderContext, deadline: &Deadline) -> Result<DynamicImage, RenderError> {
+    // Frames could have been reloaded since request was validated
+    validate_album(&data, ctx)?;
     let image_count = data.len();
-    let images_results: Vec<Result<DynamicImage, String>> = data
+    let padding = ctx.config.render.album_card_padding;
+
+    let images_results: Vec<Result<DynamicImage, RenderError>> = data
         .into_par_iter()
         .map(|card| {
-            // Each render gets its own start_time clone
-            render_card(&card, frames, start_time)
+            // All cards share deadline of the request
+            render_card(&card, ctx, deadline)
         })
         .collect();
 
@@ -26,38 +37,45 @@ pub fn render_album(data: Vec<CardRenderRequestData>, frames: &Arc<HashMap<Strin
         }
     }
 
-    let (max_width, max_height) = images.iter().fold((0, 0), |(w, h), img| {
-        (w.max(img.width()), h.max(img.height()))
-    });
+    let card_sizes: Vec<_> = images.iter().map(|img| (img.width(), img.height())).collect();
+    let (x, y, cols) = album_layout(&card_sizes, padding);
+    let (max_width, max_height) = max_card_size(&card_sizes);
 
-    let aspect_bias = 1.35;
-    let mut cols = ((aspect_bias * image_count as f32).sqrt()).ceil() as u32;
-    cols = cols.min(image_count as u32);
-    let rows = ((image_count as f32) / (cols as f32)).ceil() as u32;
-    
-    let x = cols * max_width + (cols + 1) * ALBUM_CARD_PADDING;
-    let y = rows * max_height + (rows + 1) * ALBUM_CARD_PADDING;
-    
     let mut result = ImageBuffer::new(x, y);
 
-    if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-        return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-    }
+    deadline.check()?;
 
     for (i, image) in images.iter().enumerate() {
         let idx = i as u32;
         let col = idx % cols;
         let row = idx / cols;
 
-        let x = (ALBUM_CARD_PADDING + col * (max_width + ALBUM_CARD_PADDING)) as i64;
-        let y = (ALBUM_CARD_PADDING + row * (max_height + ALBUM_CARD_PADDING)) as i64;
+        let x = (padding + col * (max_width + padding)) as i64;
+        let y = (padding + row * (max_height + padding)) as i64;
 
         overlay(&mut result, image, x, y);
 
-        if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-            return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-        }
+        deadline.check()?;
     }
 
     Ok(result.into())
-}
\ No newline at end of file
+}
+
+/// Output size and column count of album made of cards of given sizes.
+fn album_layout(card_sizes: &[(u32, u32)], padding: u32) -> (u32, u32, u32) {
+    let image_count = card_sizes.len();
+    let (max_width, max_height) = max_card_size(card_sizes);
+
+    let aspect_bias = 1.35;
+    let mut cols = ((aspect_bias * image_count as f32).sqrt()).ceil() as u32;
+    cols = cols.min(image_count as u32);
+    let rows = ((image_count as f32) / (cols as f32)).ceil() as u32;
+
+    let x = cols * max_width + (cols + 1) * padding;
+    let y = rows * max_height + (rows + 1) * padding;
+    (x, y, cols)
+}
+
+fn max_card_size(card_sizes: &[(u32, u32)]) -> (u32, u32) {
+    card_sizes.iter().fold((0, 0), |(w, h), &(width, height)| (w.max(width), h.max(height)))
+}
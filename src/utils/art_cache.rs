@@ -0,0 +1,45 @@
+use std::{sync::Arc, time::SystemTime};
+use image::{DynamicImage, ImageReader};
+
+use crate::utils::lru_cache::{CacheStats, LruCache};
+
+pub enum ArtLoadError {
+    Missing(std::io::Error),
+    Damaged(image::ImageError),
+}
+
+/// Decoded character art keyed by resolved path. Entries are invalidated when file modification time changes.
+pub struct ArtCache {
+    cache: LruCache<String, (Arc<DynamicImage>, Option<SystemTime>)>,
+}
+
+impl ArtCache {
+    pub fn new(budget: usize) -> Self {
+        ArtCache { cache: LruCache::new(budget) }
+    }
+
+    pub fn load(&self, path: &str) -> Result<Arc<DynamicImage>, ArtLoadError> {
+        let modified = std::fs::metadata(path).map_err(ArtLoadError::Missing)?.modified().ok();
+
+        if self.cache.is_enabled() {
+            if let Some((image, _)) = self.cache.get_if(&path.to_string(), |(_, cached_modified)| *cached_modified == modified) {
+                return Ok(image);
+            }
+        }
+
+        let image = ImageReader::open(path)
+            .map_err(ArtLoadError::Missing)?
+            .decode()
+            .map_err(ArtLoadError::Damaged)?;
+        let image = Arc::new(image);
+
+        if self.cache.is_enabled() {
+            self.cache.insert(path.to_string(), (image.clone(), modified), image.as_bytes().len());
+        }
+        Ok(image)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
@@ -0,0 +1,46 @@
+use crate::config::{ArtFallback, Config};
+use crate::error::RenderError;
+use crate::models::CardRenderRequestData;
+
+/// Returns paths of art that should be tried (in order) for given card: requested art first, followed by
+/// configured fallback chain.
+pub fn art_candidates(data: &CardRenderRequestData, config: &Config) -> Result<Vec<String>, RenderError> {
+    let art = &config.art;
+    let character_images = &config.paths.character_images;
+    let base = format!("{}/{}", character_images, fill_pattern(&art.base, data));
+
+    let requested = if let Some(custom_art) = data.custom_art {
+        // Custom card art (uploaded by players) takes precedence over regular character art
+        format!("{}/{}.png", config.paths.card_images, custom_art)
+    } else if data.variant == 0 {
+        base.clone()
+    } else {
+        match art.variants.iter().find(|rule| (rule.min..=rule.max).contains(&data.variant)) {
+            Some(rule) => format!("{}/{}", character_images, fill_pattern(&rule.path, data)),
+            None => return Err(RenderError::InvalidVariant(format!("variant {} is not supported", data.variant))),
+        }
+    };
+
+    let mut candidates = vec![requested];
+    for fallback in &art.fallback {
+        let path = match fallback {
+            ArtFallback::Base => base.clone(),
+            ArtFallback::Placeholder => match &art.placeholder {
+                Some(placeholder) => format!("{}/{}", character_images, placeholder),
+                None => continue,
+            },
+        };
+
+        if !candidates.contains(&path) {
+            candidates.push(path);
+        }
+    }
+
+    Ok(candidates)
+}
+
+fn fill_pattern(pattern: &str, data: &CardRenderRequestData) -> String {
+    pattern
+        .replace("{id}", &data.id.to_string())
+        .replace("{variant}", &data.variant.to_string())
+}
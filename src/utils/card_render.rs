@@ -1,94 +1,164 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
-use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageReader, Pixel, Rgba};
+use std::{borrow::Cow, sync::Arc};
+use image::{imageops::{replace, FilterType}, DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgba};
 use log::warn;
 use palette::{Srgb, Oklab, IntoColor};
 
-use crate::{config::{CDN_CHARACTER_IMAGES_PATH, RENDER_TIMEOUT}, models::CardRenderRequestData};
+use crate::error::RenderError;
+use crate::models::{CardRenderRequestData, Placement};
+use crate::utils::art_cache::{ArtCache, ArtLoadError};
+use crate::utils::art_resolver::art_candidates;
+use crate::utils::mask_cache::{Mask, MaskKey};
+use crate::utils::{Deadline, RenderContext};
+use crate::utils::frame_loader::{Frame, COLOR_LAYER, KINDLED_COLOR_LAYER, KINDLED_STATIC_LAYER, STATIC_LAYER};
 
 use rayon::prelude::*;
 
-pub fn render_card(data: &CardRenderRequestData, frames: &Arc<HashMap<String, DynamicImage>>, start_time: &Instant) -> Result<DynamicImage, String> {
-    let image_path = if data.variant == 0 {
-        format!("{}/{}.png", CDN_CHARACTER_IMAGES_PATH, data.id)
-    } else {
-        format!("{}/{}/{}{}.png", CDN_CHARACTER_IMAGES_PATH, data.id, if data.variant < 10 {'u'} else {'x'}, data.variant)
-    };
+pub fn render_card(data: &CardRenderRequestData, ctx: &RenderContext, deadline: &Deadline) -> Result<DynamicImage, RenderError> {
+    let config = &ctx.config;
 
-    let character_image = match ImageReader::open(&image_path) {
-        Ok(img) => match img.decode() {
-            Ok(img) => img,
-            Err(e) => {
-                warn!("Failed render due to likely damaged character image on path: {}. Received error: {}", image_path, e);
-                return Err(format!("failed request - failed to decode main image asset."))
-            },
-        }
-        Err(e) => {
-            warn!("Failed render due to missing character image on path: {}. Received error: {}", image_path, e);
-            return Err(format!("failed request - requested card could not be rendered due to missing main image asset."));
-        },
-    };
+    // Validate request against frame metadata before doing any expensive work
+    let frame = find_frame(data, ctx)?;
+    let (mask, decoration) = select_layers(data, frame)?;
 
-    if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-        return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-    }
-    let frame = data.frame_type.to_string();
+    let character_image = load_character_image(&ctx.art_cache, &art_candidates(data, config)?)?;
 
-    let (mask, decoration) = if data.kindled {
-        (frames.get(&format!("{}-kindled-color", frame)), frames.get(&format!("{}-kindled-static", frame)))
-    } else {
-        (frames.get(&format!("{}-color", frame)), frames.get(&format!("{}-static", frame)))
-    };
+    deadline.check()?;
 
-    if mask.is_none() {
-        return Err(format!("failed request - \"{}\" frame type is invalid (doesn't exist)", frame));
-    }
-
-    if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-        return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-    }
-
-    let mask = recolor_mask(mask.unwrap(), data.dye);
-    let mut result = ImageBuffer::new(mask.width(), mask.height());
-
-    if let Err(_) = result.copy_from(&character_image, 0, 0) {
-        return Err(format!("server error - failed to copy character image onto final canvas"));
-    };
+    let mask_key = MaskKey { generation: ctx.frames.generation(), frame: data.frame_type, kindled: data.kindled, dye: data.dye };
+    let mask = ctx.mask_cache.get_or_recolor(mask_key, || recolor_mask(mask, data.dye, deadline))?;
+    let mut result = ImageBuffer::new(frame.details.width, frame.details.height);
 
-    if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-        return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-    }
+    let (character_image, x, y) = place_character_image(&character_image, data.placement.unwrap_or_default(), result.width(), result.height());
+    let x = x + data.offset_x.unwrap_or(0) as i64;
+    let y = y + data.offset_y.unwrap_or(0) as i64;
+    replace(&mut result, &character_image.to_rgba8(), x, y);
 
+    deadline.check()?;
 
     if let Some(decoration) = decoration {
-        result.par_enumerate_pixels_mut().for_each(|(x, y, p)| {
+        result.par_enumerate_pixels_mut().try_for_each(|(x, y, p)| {
+            if x == 0 {
+                deadline.check()?;
+            }
             let decoration_pixel = decoration.get_pixel(x, y);
             if decoration_pixel[3] == 0 {
-                return
+                return Ok(())
             }
             p.blend(&decoration_pixel);
-        })
+            Ok(())
+        })?;
     }
 
-    if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-        return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-    }
+    deadline.check()?;
 
-    result.par_enumerate_pixels_mut().for_each(|(x, y, p)| {
+    result.par_enumerate_pixels_mut().try_for_each(|(x, y, p)| {
+        if x == 0 {
+            deadline.check()?;
+        }
         let mask_pixel = mask.get_pixel(x, y);
         if mask_pixel[3] == 0 {
-            return
+            return Ok(())
         }
-        p.blend(&mask_pixel);
-    });
+        p.blend(mask_pixel);
+        Ok(())
+    })?;
 
-    if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-        return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-    }
+    deadline.check()?;
 
     Ok(result.into())
 }
 
-fn recolor_mask(mask: &DynamicImage, dye: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+/// Size of rendered card (it always matches its frame), known before anything is rendered. Also checks that card can
+/// be rendered with its frame at all, so invalid requests are rejected before any work starts.
+pub fn card_size(data: &CardRenderRequestData, ctx: &RenderContext) -> Result<(u32, u32), RenderError> {
+    let frame = find_frame(data, ctx)?;
+    select_layers(data, frame)?;
+    Ok((frame.details.width, frame.details.height))
+}
+
+fn find_frame<'a>(data: &CardRenderRequestData, ctx: &'a RenderContext) -> Result<&'a Frame, RenderError> {
+    match ctx.frames.get(data.frame_type) {
+        Some(frame) => Ok(frame),
+        None => Err(RenderError::InvalidFrame(format!("frame type {} doesn't exist", data.frame_type))),
+    }
+}
+
+/// Loads first art from candidates that exists and decodes properly.
+fn load_character_image(art_cache: &ArtCache, candidates: &[String]) -> Result<Arc<DynamicImage>, RenderError> {
+    let mut damaged = false;
+
+    for (i, image_path) in candidates.iter().enumerate() {
+        match art_cache.load(image_path) {
+            Ok(img) => {
+                if i > 0 {
+                    warn!("Requested art {} is unavailable, rendering with fallback art from path: {}", candidates[0], image_path);
+                }
+                return Ok(img);
+            }
+            Err(ArtLoadError::Damaged(e)) => {
+                warn!("Failed to use likely damaged character image on path: {}. Received error: {}", image_path, e);
+                damaged = true;
+            }
+            Err(ArtLoadError::Missing(e)) => warn!("Failed to use missing character image on path: {}. Received error: {}", image_path, e),
+        }
+    }
+
+    if damaged {
+        Err(RenderError::AssetDamaged("failed to decode main image asset".to_string()))
+    } else {
+        Err(RenderError::AssetMissing("requested card could not be rendered due to missing main image asset".to_string()))
+    }
+}
+
+/// Resamples character art according to placement mode. Returns art together with its (centered) position on canvas.
+fn place_character_image(image: &DynamicImage, placement: Placement, width: u32, height: u32) -> (Cow<'_, DynamicImage>, i64, i64) {
+    if image.dimensions() == (width, height) {
+        return (Cow::Borrowed(image), 0, 0);
+    }
+
+    let image = match placement {
+        Placement::None => return (Cow::Borrowed(image), 0, 0),
+        Placement::Fit => image.resize(width, height, FilterType::Lanczos3),
+        Placement::Fill => image.resize_to_fill(width, height, FilterType::Lanczos3),
+        Placement::Stretch => image.resize_exact(width, height, FilterType::Lanczos3),
+    };
+
+    let x = (width as i64 - image.width() as i64) / 2;
+    let y = (height as i64 - image.height() as i64) / 2;
+    (Cow::Owned(image), x, y)
+}
+
+/// Picks mask (color) and decoration (static) layers according to frame metadata.
+fn select_layers<'a>(data: &CardRenderRequestData, frame: &'a Frame) -> Result<(&'a DynamicImage, Option<&'a DynamicImage>), RenderError> {
+    let details = &frame.details;
+    if data.kindled && !details.extendable {
+        return Err(RenderError::InvalidFrame(format!("\"{}\" frame cannot be kindled", details.name)));
+    }
+
+    let (mask_layer, decoration_layer) = if data.kindled {
+        (KINDLED_COLOR_LAYER, KINDLED_STATIC_LAYER)
+    } else {
+        (COLOR_LAYER, STATIC_LAYER)
+    };
+
+    // Loader guarantees that every layer implied by model flags is present
+    let mask = match frame.layer(mask_layer) {
+        Some(mask) => mask,
+        None => return Err(RenderError::Internal(format!("\"{}\" frame is missing its \"{}\" layer", details.name, mask_layer))),
+    };
+    let decoration = if details.static_model {
+        match frame.layer(decoration_layer) {
+            Some(decoration) => Some(decoration),
+            None => return Err(RenderError::Internal(format!("\"{}\" frame is missing its \"{}\" layer", details.name, decoration_layer))),
+        }
+    } else {
+        None
+    };
+
+    Ok((mask, decoration))
+}
+
+fn recolor_mask(mask: &DynamicImage, dye: u32, deadline: &Deadline) -> Result<Mask, RenderError> {
     // Dye int -> Oklab
     let overlay_rgb = Srgb::new(
         ((dye >> 16) & 0xFF) as f32 / 255.0,
@@ -107,12 +177,15 @@ fn recolor_mask(mask: &DynamicImage, dye: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>>
 
     let mut recolored = mask.to_rgba8();
 
-    recolored.par_enumerate_pixels_mut().for_each(|(_, _, pixel)| {
+    recolored.par_enumerate_pixels_mut().try_for_each(|(x, _, pixel)| {
+        if x == 0 {
+            deadline.check()?;
+        }
         let (r, g, b, a) = (pixel[0], pixel[1], pixel[2], pixel[3]);
 
         if a == 0 {
             //*pixel = Rgba([0, 0, 0, 0]);
-            return;
+            return Ok(());
         }
 
         let orig_rgb = Srgb::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
@@ -141,7 +214,8 @@ fn recolor_mask(mask: &DynamicImage, dye: u32) -> ImageBuffer<Rgba<u8>, Vec<u8>>
         let final_rgb = final_rgb.into_format::<u8>();
 
         *pixel = Rgba([final_rgb.red, final_rgb.green, final_rgb.blue, a]);
-    });
+        Ok(())
+    })?;
 
-    recolored
+    Ok(recolored)
 }
\ No newline at end of file
@@ -0,0 +1,76 @@
+use std::sync::{atomic::{AtomicU8, Ordering}, Arc};
+use std::time::{Duration, Instant};
+use log::debug;
+
+use crate::error::RenderError;
+
+const RUNNING: u8 = 0;
+const TIMED_OUT: u8 = 1;
+const CANCELLED: u8 = 2;
+
+/// Deadline of single render request, passed down to every render step. Render stops (at the next check)
+/// when it takes longer than allowed or when request gets cancelled, e.g. because client disconnected.
+/// Clones share their state, so cancelling any of them stops all of them.
+#[derive(Clone)]
+pub struct Deadline {
+    start: Instant,
+    timeout: f32, // in seconds
+    state: Arc<AtomicU8>,
+}
+
+impl Deadline {
+    pub fn new(start: Instant, timeout: f32) -> Self {
+        Deadline { start, timeout, state: Arc::new(AtomicU8::new(RUNNING)) }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Fails once deadline has passed or request was cancelled.
+    pub fn check(&self) -> Result<(), RenderError> {
+        match self.state.load(Ordering::Relaxed) {
+            TIMED_OUT => Err(RenderError::Timeout(self.timeout)),
+            CANCELLED => Err(RenderError::Cancelled),
+            _ if self.start.elapsed().as_secs_f32() >= self.timeout => {
+                let _ = self.state.compare_exchange(RUNNING, TIMED_OUT, Ordering::Relaxed, Ordering::Relaxed);
+                self.check()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Cheaper than [`Deadline::check`] (doesn't read clock), meant for hot loops that call `check` only once in a while.
+    pub fn is_expired(&self) -> bool {
+        self.state.load(Ordering::Relaxed) != RUNNING
+    }
+
+    pub fn cancel(&self) {
+        let _ = self.state.compare_exchange(RUNNING, CANCELLED, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    /// Cancels deadline when dropped before [`CancelGuard::disarm`] is called. Render task holds it across awaits,
+    /// so render is cancelled when task is aborted - once every client waiting for it disconnected.
+    pub fn cancel_on_drop(&self) -> CancelGuard {
+        CancelGuard { deadline: Some(self.clone()) }
+    }
+}
+
+pub struct CancelGuard {
+    deadline: Option<Deadline>,
+}
+
+impl CancelGuard {
+    pub fn disarm(mut self) {
+        self.deadline = None;
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(deadline) = &self.deadline {
+            debug!("Render was abandoned (all clients disconnected) after {:?}, cancelling it", deadline.elapsed());
+            deadline.cancel();
+        }
+    }
+}
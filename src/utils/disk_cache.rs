@@ -0,0 +1,114 @@
+use std::{io, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}};
+use axum::body::Bytes;
+use serde::Serialize;
+
+use crate::utils::render_store;
+
+/// Content-addressed cache of encoded renders on disk, keyed by render fingerprint (see
+/// [`crate::utils::render_fingerprint`], which covers render kind too), so every render kind is cached without
+/// caller having to name it, and two different requests can never share entry. Entries are sharded by first two
+/// bytes of fingerprint (`ab/cd/abcd....png`), so no directory grows too large.
+pub struct DiskCache {
+    path: PathBuf,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    writes: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct DiskCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub writes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedRender {
+    pub fingerprint: String,
+    pub size: u64,
+    pub modified: u64, // unix timestamp in seconds
+    pub accessed: u64, // unix timestamp in seconds
+}
+
+impl DiskCache {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        DiskCache { path: path.into(), hits: AtomicU64::new(0), misses: AtomicU64::new(0), writes: AtomicU64::new(0) }
+    }
+
+    pub fn entry_path(&self, fingerprint: &str) -> PathBuf {
+        self.shard_path(fingerprint).join(format!("{}.png", fingerprint))
+    }
+
+    pub async fn get(&self, fingerprint: &str) -> Option<Bytes> {
+        let png = render_store::read_file(self.entry_path(fingerprint)).await;
+        let counter = if png.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        png
+    }
+
+    pub async fn insert(&self, fingerprint: &str, png: &[u8]) -> io::Result<()> {
+        let shard_path = self.shard_path(fingerprint);
+        tokio::fs::create_dir_all(&shard_path).await?;
+        render_store::write_atomic(&shard_path, &format!("{}.png", fingerprint), png).await?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns whether there was anything to remove.
+    pub async fn remove(&self, fingerprint: &str) -> io::Result<bool> {
+        match tokio::fs::remove_file(self.entry_path(fingerprint)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Every cached render, in no particular order.
+    pub async fn list(&self) -> io::Result<Vec<CachedRender>> {
+        let mut renders = Vec::new();
+        for shard in self.shards().await? {
+            let mut entries = tokio::fs::read_dir(&shard).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().to_string();
+                // Hidden files are temporary files
+                let Some(fingerprint) = name.strip_suffix(".png").filter(|_| !name.starts_with('.')) else { continue };
+                let Ok(file) = entry.metadata().await else { continue };
+                let (modified, accessed) = render_store::file_times(&file);
+                renders.push(CachedRender { fingerprint: fingerprint.to_string(), size: file.len(), modified, accessed });
+            }
+        }
+        Ok(renders)
+    }
+
+    /// Directories entries are stored in.
+    pub async fn shards(&self) -> io::Result<Vec<PathBuf>> {
+        let mut shards = Vec::new();
+        for dir in subdirectories(&self.path).await? {
+            shards.extend(subdirectories(&dir).await?);
+        }
+        Ok(shards)
+    }
+
+    pub fn stats(&self) -> DiskCacheStats {
+        DiskCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+        }
+    }
+
+    fn shard_path(&self, fingerprint: &str) -> PathBuf {
+        self.path.join(fingerprint.get(0..2).unwrap_or("00")).join(fingerprint.get(2..4).unwrap_or("00"))
+    }
+}
+
+async fn subdirectories(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = tokio::fs::read_dir(path).await?;
+    let mut dirs = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            dirs.push(entry.path());
+        }
+    }
+    Ok(dirs)
+}
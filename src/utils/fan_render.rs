@@ -1,10 +1,9 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
 use image::{imageops::overlay, DynamicImage, GenericImageView, ImageBuffer, Rgba};
-use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+use imageproc::geometric_transformations::{warp_with, Interpolation, Projection};
 
-use crate::{config::{FAN_CARD_ANGLE, FAN_CIRCLE_CENTER_DISTANCE, RENDER_TIMEOUT}};
+use crate::error::{check_canvas_size, check_card_count, RenderError};
 use crate::models::CardRenderRequestData;
-use crate::utils::render_card;
+use crate::utils::{card_size, render_card, Deadline, RenderContext};
 
 use rayon::prelude::*;
 
@@ -20,42 +19,46 @@ struct Position {
     angle: f32
 }
 
-pub fn render_fan(data: Vec<CardRenderRequestData>, frames: &Arc<HashMap<String, DynamicImage>>, start_time: &Instant) -> Result<DynamicImage, String> {
-    let image_count = data.len();
+/// Card count and output size of fan are known up front from frame sizes, so oversized fans are rejected before any
+/// card is rendered (and before request takes place in render pool).
+pub fn validate_fan(data: &[CardRenderRequestData], ctx: &RenderContext) -> Result<(), RenderError> {
+    check_card_count(data.len(), ctx.config.limits.max_fan_cards, "fan")?;
 
-    // Precompute positions (it's cheap, no need to parallelize)
-    let positions: Vec<Position> = (0..image_count)
-        .map(|i| {
-            let position_index = i as f32 - image_count as f32 / 2.0 + 0.5;
-            let angle = (FAN_CARD_ANGLE * position_index).to_radians();
-            Position {
-                x: FAN_CIRCLE_CENTER_DISTANCE * angle.sin(),
-                y: (FAN_CIRCLE_CENTER_DISTANCE * angle.cos() - FAN_CIRCLE_CENTER_DISTANCE).abs(),
-                angle,
-            }
+    let rotated_sizes = data.iter()
+        .zip(fan_positions(data.len(), ctx))
+        .map(|(card, pos)| {
+            let (width, height) = card_size(card, ctx)?;
+            let (quarter_turns, rotation_angle) = card_rotation(&pos);
+            let (width, height) = if quarter_turns == 0 { (width, height) } else { (height, width) };
+            let (width, height) = rotated_size(width, height, rotation_angle);
+            Ok((width, height, pos))
         })
-        .collect();
+        .collect::<Result<Vec<_>, RenderError>>()?;
+    let (width, height, _) = fan_layout(&rotated_sizes);
+    check_canvas_size(width, height, ctx.config.limits.max_pixels)
+}
+
+pub fn render_fan(data: Vec<CardRenderRequestData>, ctx: &RenderContext, deadline: &Deadline) -> Result<DynamicImage, RenderError> {
+    // Frames could have been reloaded since request was validated
+    validate_fan(&data, ctx)?;
+    let image_count = data.len();
+    let positions = fan_positions(image_count, ctx);
 
     // Parallel render + rotation
     let images_results: Vec<_> = data
         .into_par_iter()
         .zip(positions.par_iter())
         .map(|(card, pos)| {
-            let image = render_card(&card, frames, start_time)?;
+            let image = render_card(&card, ctx, deadline)?;
 
-            let mut rotation_angle = pos.angle.to_degrees().round();
-            let mut image = image;
+            let (quarter_turns, rotation_angle) = card_rotation(pos);
+            let image = match quarter_turns {
+                1 => image.rotate90(),
+                -1 => image.rotate270(),
+                _ => image,
+            };
 
-            if rotation_angle > 45.0 && rotation_angle < 135.0 {
-                rotation_angle -= 90.0;
-                image = image.rotate90();
-            }
-            if rotation_angle < -45.0 && rotation_angle > -135.0 {
-                rotation_angle += 90.0;
-                image = image.rotate270();
-            }
-
-            let image = rotate_image(image, rotation_angle);
+            let image = rotate_image(image, rotation_angle, deadline)?;
             Ok(IndexedImage {
                 image,
                 center_offset: *pos,
@@ -71,25 +74,11 @@ pub fn render_fan(data: Vec<CardRenderRequestData>, frames: &Arc<HashMap<String,
         }
     }
 
-    if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-        return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-    }
+    deadline.check()?;
 
     // Calculate output image size
-    let center_height = images[image_count / 2].image.height() / 2;
-    let x = {
-        let mut x = images.iter().map(|img| img.center_offset.x as u32).max().unwrap();
-        x *= 2;
-        x += images.iter().max_by(|a, b| a.center_offset.x.partial_cmp(&b.center_offset.x).unwrap()).unwrap().image.width() / 2;
-        x += images.iter().min_by(|a, b| a.center_offset.x.partial_cmp(&b.center_offset.x).unwrap()).unwrap().image.width() / 2;
-        x
-    };
-    let y = {
-        let mut y = center_height;
-        y += images[0].image.height().max(images[image_count - 1].image.height()) / 2;
-        y += images[0].center_offset.y.abs().ceil() as u32;
-        y
-    };
+    let sizes: Vec<_> = images.iter().map(|img| (img.image.width(), img.image.height(), img.center_offset)).collect();
+    let (x, y, center_height) = fan_layout(&sizes);
 
     let mut result = ImageBuffer::new(x, y);
 
@@ -109,30 +98,103 @@ pub fn render_fan(data: Vec<CardRenderRequestData>, frames: &Arc<HashMap<String,
 
         overlay(&mut result, &image.image, draw_x, draw_y);
 
-        if start_time.elapsed().as_secs_f32() >= RENDER_TIMEOUT {
-            return Err(format!("gateway timeout - asset render took more than {} seconds", RENDER_TIMEOUT));
-        }
+        deadline.check()?;
     }
 
     Ok(result.into())
 }
 
-fn rotate_image(image: DynamicImage, angle: f32) -> DynamicImage {
-    let (width, height) = image.dimensions();
+/// Position of each card on circle fan is laid out along.
+fn fan_positions(image_count: usize, ctx: &RenderContext) -> Vec<Position> {
+    let card_angle = ctx.config.render.fan_card_angle;
+    let center_distance = ctx.config.render.fan_circle_center_distance;
+
+    // It's cheap, no need to parallelize
+    (0..image_count)
+        .map(|i| {
+            let position_index = i as f32 - image_count as f32 / 2.0 + 0.5;
+            let angle = (card_angle * position_index).to_radians();
+            Position {
+                x: center_distance * angle.sin(),
+                y: (center_distance * angle.cos() - center_distance).abs(),
+                angle,
+            }
+        })
+        .collect()
+}
+
+/// Output size (and vertical center) of fan made of already rotated cards of given sizes.
+fn fan_layout(cards: &[(u32, u32, Position)]) -> (u32, u32, u32) {
+    let image_count = cards.len();
+    let center_height = cards[image_count / 2].1 / 2;
+    let x = {
+        let mut x = cards.iter().map(|(_, _, pos)| pos.x as u32).max().unwrap();
+        x *= 2;
+        x += cards.iter().max_by(|a, b| a.2.x.partial_cmp(&b.2.x).unwrap()).unwrap().0 / 2;
+        x += cards.iter().min_by(|a, b| a.2.x.partial_cmp(&b.2.x).unwrap()).unwrap().0 / 2;
+        x
+    };
+    let y = {
+        let mut y = center_height;
+        y += cards[0].1.max(cards[image_count - 1].1) / 2;
+        y += cards[0].2.y.abs().ceil() as u32;
+        y
+    };
+    (x, y, center_height)
+}
+
+/// Splits card angle into quarter turns (done losslessly) and remaining rotation in degrees.
+fn card_rotation(pos: &Position) -> (i32, f32) {
+    let rotation_angle = pos.angle.to_degrees().round();
+    if rotation_angle > 45.0 && rotation_angle < 135.0 {
+        return (1, rotation_angle - 90.0);
+    }
+    if rotation_angle < -45.0 && rotation_angle > -135.0 {
+        return (-1, rotation_angle + 90.0);
+    }
+    (0, rotation_angle)
+}
+
+fn rotated_size(width: u32, height: u32, angle: f32) -> (u32, u32) {
     let rad = angle.to_radians();
     let new_width = (width as f32 * rad.cos().abs() + height as f32 * rad.sin().abs()).ceil() as u32;
     let new_height = (width as f32 * rad.sin().abs() + height as f32 * rad.cos().abs()).ceil() as u32;
+    (new_width, new_height)
+}
+
+fn rotate_image(image: DynamicImage, angle: f32, deadline: &Deadline) -> Result<DynamicImage, RenderError> {
+    let (width, height) = image.dimensions();
+    let rad = angle.to_radians();
+    let (new_width, new_height) = rotated_size(width, height, angle);
     let mut result = ImageBuffer::new(new_width, new_height);
     let diff_x = (new_width - width) / 2;
     let diff_y = (new_height - height) / 2;
     
-    result.par_enumerate_pixels_mut()
-          .filter(|(x, y, _)| *x >= diff_x && *x < diff_x + width && *y < diff_y + height && *y >= diff_y)
-          .for_each(|(x, y, p)| {
-        *p = image.get_pixel((x - diff_x) as u32, (y - diff_y) as u32);
-    });
+    result.par_enumerate_pixels_mut().try_for_each(|(x, y, p)| {
+        if x == 0 {
+            deadline.check()?;
+        }
+        if x >= diff_x && x < diff_x + width && y < diff_y + height && y >= diff_y {
+            *p = image.get_pixel(x - diff_x, y - diff_y);
+        }
+        Ok(())
+    })?;
     // result.copy_from(&image, diff_x, diff_y).unwrap();
 
-    rotate_about_center(&result, rad, Interpolation::Bicubic, Rgba::from([0, 0, 0, 0])).into()
-    
-}
\ No newline at end of file
+    // Same as `rotate_about_center`, except that source stops being sampled (every pixel maps outside of it)
+    // once deadline fires, so rotation of cancelled render finishes almost instantly
+    let (cx, cy) = (new_width as f32 / 2.0, new_height as f32 / 2.0);
+    let projection = (Projection::translate(cx, cy) * Projection::rotate(rad) * Projection::translate(-cx, -cy)).invert();
+    let rotated = warp_with(&result, |x, y| {
+        if x == 0.0 {
+            let _ = deadline.check();
+        }
+        if deadline.is_expired() {
+            return (-1.0, -1.0);
+        }
+        projection * (x, y)
+    }, Interpolation::Bicubic, Rgba::from([0, 0, 0, 0]));
+
+    deadline.check()?;
+    Ok(rotated.into())
+}
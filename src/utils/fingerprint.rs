@@ -0,0 +1,88 @@
+use std::{collections::BTreeSet, time::UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+
+use crate::models::CardRenderRequestData;
+use crate::utils::art_resolver::art_candidates;
+use crate::utils::RenderContext;
+
+/// Bump whenever change in rendering code alters its output, so renders cached by older version are never reused.
+pub const RENDERER_VERSION: u32 = 2;
+
+/// Identifies exact render output: render kind (fan and album share request structure, so their hashes can be
+/// equal), canonical request hash, renderer version, layout settings and versions of every asset render can touch
+/// (frames digest and modification time & size of each art candidate, including fallbacks). Same fingerprint always
+/// means byte for byte same PNG.
+pub fn render_fingerprint(kind: &str, hash: &str, cards: &[&CardRenderRequestData], ctx: &RenderContext) -> String {
+    let render = &ctx.config.render;
+    let mut hasher = Sha256::new();
+    hasher.update(kind);
+    hasher.update(b"\0");
+    hasher.update(hash);
+    hasher.update(RENDERER_VERSION.to_le_bytes());
+    hasher.update(format!("|{}|{}|{}|", render.fan_card_angle, render.fan_circle_center_distance, render.album_card_padding));
+    hasher.update(ctx.frames.version());
+
+    // Invalid cards (e.g. unsupported variant) have no candidates, such requests fail anyway
+    let paths: BTreeSet<String> = cards.iter()
+        .flat_map(|card| art_candidates(card, &ctx.config).unwrap_or_default())
+        .collect();
+    for path in paths {
+        hasher.update(b"\0");
+        hasher.update(&path);
+        match std::fs::metadata(&path) {
+            Ok(metadata) => {
+                let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).unwrap_or_default();
+                hasher.update(modified.as_nanos().to_le_bytes());
+                hasher.update(metadata.len().to_le_bytes());
+            }
+            Err(_) => hasher.update(b"missing"),
+        }
+    }
+
+    hex(&hasher.finalize()[..16])
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::config::Config;
+    use crate::models::{FanRenderRequestData, FrameId};
+    use crate::utils::{encode_hash, ArtCache, FrameRegistry, MaskCache};
+
+    #[test]
+    fn fingerprint_depends_on_render_kind() {
+        let ctx = RenderContext {
+            config: Arc::new(Config::default()),
+            frames: Arc::new(FrameRegistry::default()),
+            art_cache: Arc::new(ArtCache::new(0)),
+            mask_cache: Arc::new(MaskCache::new(0)),
+        };
+        let card = CardRenderRequestData {
+            id: 1,
+            variant: 0,
+            dye: 0,
+            kindled: false,
+            frame_type: FrameId(0),
+            offset_x: None,
+            offset_y: None,
+            placement: None,
+            custom_art: None,
+            save_name: None,
+        };
+        let request = FanRenderRequestData { cards: vec![card.clone(), card], save_name: None };
+        let cards: Vec<_> = request.cards.iter().collect();
+
+        // Fan and album requests with the same cards have the same hash
+        let hash = encode_hash(&request);
+        let fan = render_fingerprint("fan", &hash, &cards, &ctx);
+        let album = render_fingerprint("album", &hash, &cards, &ctx);
+        assert_ne!(fan, album);
+        assert_eq!(fan, render_fingerprint("fan", &hash, &cards, &ctx));
+    }
+}
@@ -1,24 +1,309 @@
-use std::{collections::HashMap};
+use std::{collections::HashMap, fmt, path::Path, sync::{atomic::{AtomicU64, Ordering}, Arc, RwLock}};
 use image::{DynamicImage, load_from_memory};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::config::{CDN_FRAMES_PATH};
+use crate::models::FrameId;
+use crate::utils::fingerprint::hex;
 
-pub fn load_frames() -> HashMap<String, DynamicImage> {
-    let mut frames = HashMap::new();
-    for entry in std::fs::read_dir(CDN_FRAMES_PATH).unwrap() {
-        let entry = entry.unwrap();
-        let path = entry.path();
+pub const FRAME_MANIFEST_FILE: &str = "frame.toml";
+
+/// Manifests of frames that were built into the service before manifests were introduced, used for their
+/// directories that don't have one yet, so existing deployments keep serving `frame_type` 0 and 1.
+const BUILT_IN_MANIFESTS: [(&str, &str); 2] = [
+    ("moonweaver", include_str!("../../frames/moonweaver/frame.toml")),
+    ("essentia", include_str!("../../frames/essentia/frame.toml")),
+];
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+pub const COLOR_LAYER: &str = "color";
+pub const STATIC_LAYER: &str = "static";
+pub const KINDLED_COLOR_LAYER: &str = "kindled-color";
+pub const KINDLED_STATIC_LAYER: &str = "kindled-static";
+
+/// Contents of `frame.toml` manifest placed inside each frame directory.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FrameDetails {
+    pub name: String,
+    pub id: u8, // value used in render requests (frame_type), must never change once frame is public
+    pub static_model: bool,
+    pub color_model: bool,
+    pub extendable: bool, // whether can be kindled (has that improved version)
+    pub width: u32,
+    pub height: u32,
+    /// Layer name -> file name (relative to frame directory). Overrides default `<layer>.png` file name
+    /// of layers implied by model flags.
+    #[serde(default)]
+    pub layers: HashMap<String, String>,
+}
+
+impl FrameDetails {
+    /// Layers this frame is expected to provide (based on model flags), together with their file names.
+    pub fn expected_layers(&self) -> Vec<(&'static str, String)> {
+        let mut layers = Vec::new();
+        if self.color_model {
+            layers.push(COLOR_LAYER);
+            if self.extendable {
+                layers.push(KINDLED_COLOR_LAYER);
+            }
+        }
+        if self.static_model {
+            layers.push(STATIC_LAYER);
+            if self.extendable {
+                layers.push(KINDLED_STATIC_LAYER);
+            }
+        }
+
+        layers.into_iter()
+            .map(|layer| (layer, self.layers.get(layer).cloned().unwrap_or_else(|| format!("{}.png", layer))))
+            .collect()
+    }
+}
+
+pub struct Frame {
+    pub details: FrameDetails,
+    layers: HashMap<String, DynamicImage>,
+    digest: Vec<u8>, // of manifest and layer files
+}
+
+impl Frame {
+    pub fn layer(&self, name: &str) -> Option<&DynamicImage> {
+        self.layers.get(name)
+    }
+}
+
+#[derive(Default)]
+pub struct FrameRegistry {
+    frames: HashMap<FrameId, Frame>,
+    generation: u64, // unique per load, lets caches tell apart frames from before and after reload
+    version: String, // digest of all frame files, unlike generation it's stable across reloads and restarts
+}
+
+impl FrameRegistry {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn get(&self, id: FrameId) -> Option<&Frame> {
+        self.frames.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl fmt::Debug for FrameRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.frames.values().map(|frame| (frame.details.id, frame.details.name.as_str())).collect();
+        names.sort();
+        f.debug_map().entries(names).finish()
+    }
+}
+
+/// Holds currently active frame registry. Renders grab a snapshot with [`FrameStore::current`], so swapping
+/// in freshly reloaded registry never affects renders that are already in progress.
+pub struct FrameStore {
+    current: RwLock<Arc<FrameRegistry>>,
+}
+
+impl FrameStore {
+    pub fn new(registry: FrameRegistry) -> Self {
+        FrameStore { current: RwLock::new(Arc::new(registry)) }
+    }
+
+    pub fn current(&self) -> Arc<FrameRegistry> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn replace(&self, registry: FrameRegistry) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(registry);
+    }
+}
+
+/// Builds frame registry out of every directory (under `frames_path`) that contains `frame.toml` manifest (or is
+/// one of built-in frames). Registry without any frame is an error, nothing could be rendered with it.
+///
+/// Each frame is validated against its manifest (all layers implied by model flags must exist, decode and
+/// match declared size) and results are logged as one consolidated report. In `strict` mode any broken frame
+/// makes whole load fail, otherwise only broken frames are left out of the registry.
+pub fn load_frames(frames_path: &str, strict: bool) -> Result<FrameRegistry, String> {
+    let entries = std::fs::read_dir(frames_path).map_err(|e| format!("failed to read frames directory {}: {}", frames_path, e))?;
+
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| format!("failed to read frames directory {}: {}", frames_path, e))?.path();
         if path.is_dir() {
-            for file in std::fs::read_dir(path).unwrap() {
-                let path = file.unwrap().path();
-                if path.is_file() && path.to_str().unwrap().ends_with(".png") {
-                    let file_buffer = std::fs::read(&path).unwrap();
-                    let img = load_from_memory(&file_buffer).unwrap();
-                    let name = format!("{}-{}", entry.file_name().into_string().unwrap(), path.file_stem().unwrap().to_str().unwrap());
-                    frames.insert(name, img);
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+
+    let mut registry = FrameRegistry {
+        generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+        ..Default::default()
+    };
+    let mut report = Vec::new();
+    let mut broken = 0;
+
+    for dir in dirs {
+        let manifest_path = dir.join(FRAME_MANIFEST_FILE);
+        let mut built_in = None;
+        let manifest = if manifest_path.is_file() {
+            std::fs::read_to_string(&manifest_path).map_err(|e| format!("failed to read {} manifest: {}", FRAME_MANIFEST_FILE, e))
+        } else {
+            let name = dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            match BUILT_IN_MANIFESTS.iter().find(|(built_in, _)| *built_in == name) {
+                Some((_, manifest)) => {
+                    built_in = Some(name);
+                    Ok(manifest.to_string())
+                }
+                None => {
+                    report.push(format!("{}: skipped", dir.display()));
+                    report.push(format!("    warning: directory has no {} manifest", FRAME_MANIFEST_FILE));
+                    continue;
+                }
+            }
+        };
+
+        let mut result = load_frame(&dir, manifest);
+        if let Some(name) = built_in {
+            result.warnings.insert(0, format!("directory has no {} manifest, built-in one is used (copy frames/{}/{} from repository into it)", FRAME_MANIFEST_FILE, name, FRAME_MANIFEST_FILE));
+        }
+
+        if let Ok(frame) = &result.frame {
+            if let Some(existing) = registry.frames.get(&FrameId(frame.details.id)) {
+                result.frame = Err(vec![format!("id {} is already taken by \"{}\" frame", frame.details.id, existing.details.name)]);
+            }
+        }
+
+        match result.frame {
+            Ok(frame) => {
+                report.push(format!("{} ({}): ok", dir.display(), frame.details.id));
+                registry.frames.insert(FrameId(frame.details.id), frame);
+            }
+            Err(problems) => {
+                broken += 1;
+                report.push(format!("{}: BROKEN", dir.display()));
+                report.extend(problems.into_iter().map(|problem| format!("    error: {}", problem)));
+            }
+        }
+        report.extend(result.warnings.into_iter().map(|warning| format!("    warning: {}", warning)));
+    }
+
+    let report = format!("Frame validation report for {}:\n  {}", frames_path, report.join("\n  "));
+    if broken == 0 {
+        info!("{}", report);
+    } else if strict {
+        error!("{}", report);
+        return Err(format!("{} frame(s) failed validation (strict mode)", broken));
+    } else {
+        warn!("{}", report);
+        warn!("{} broken frame(s) were disabled (lenient mode).", broken);
+    }
+
+    if registry.is_empty() {
+        return Err(format!("no frames were found in {}", frames_path));
+    }
+
+    let mut ids: Vec<_> = registry.frames.keys().copied().collect();
+    ids.sort();
+    let mut hasher = Sha256::new();
+    for id in ids {
+        hasher.update([id.0]);
+        hasher.update(&registry.frames[&id].digest);
+    }
+    registry.version = hex(&hasher.finalize()[..8]);
+
+    info!("Loaded {} frame(s) (version {}): {:?}", registry.len(), registry.version, registry);
+    Ok(registry)
+}
+
+struct FrameLoadResult {
+    frame: Result<Frame, Vec<String>>,
+    warnings: Vec<String>,
+}
+
+fn load_frame(dir: &Path, manifest: Result<String, String>) -> FrameLoadResult {
+    let mut warnings = Vec::new();
+
+    let manifest = match manifest {
+        Ok(manifest) => manifest,
+        Err(e) => return FrameLoadResult { frame: Err(vec![e]), warnings },
+    };
+    let details: FrameDetails = match toml::from_str(&manifest) {
+        Ok(details) => details,
+        Err(e) => return FrameLoadResult { frame: Err(vec![format!("failed to parse {} manifest: {}", FRAME_MANIFEST_FILE, e)]), warnings },
+    };
+
+    let mut digest = Sha256::new();
+    digest.update(manifest.as_bytes());
+
+    let mut problems = Vec::new();
+    if details.width == 0 || details.height == 0 {
+        problems.push(format!("declared size {}x{} is invalid", details.width, details.height));
+    }
+    if !details.color_model {
+        problems.push("frame must have color model (it provides card mask)".to_string());
+    }
+
+    let expected = details.expected_layers();
+    for layer in details.layers.keys() {
+        if !expected.iter().any(|(name, _)| name == layer) {
+            problems.push(format!("manifest overrides \"{}\" layer which is not implied by model flags", layer));
+        }
+    }
+
+    let mut layers = HashMap::new();
+    for (layer, file_name) in &expected {
+        let path = dir.join(file_name);
+        let img = match std::fs::read(&path) {
+            Ok(file_buffer) => match load_from_memory(&file_buffer) {
+                Ok(img) => {
+                    digest.update(&file_buffer);
+                    img
                 }
+                Err(e) => {
+                    problems.push(format!("\"{}\" layer ({}) failed to decode: {}", layer, file_name, e));
+                    continue;
+                }
+            },
+            Err(e) => {
+                problems.push(format!("\"{}\" layer ({}) is missing: {}", layer, file_name, e));
+                continue;
             }
+        };
+
+        if img.width() != details.width || img.height() != details.height {
+            problems.push(format!("\"{}\" layer ({}) is {}x{} but frame declares {}x{}", layer, file_name, img.width(), img.height(), details.width, details.height));
+            continue;
         }
+        layers.insert(layer.to_string(), img);
+    }
+
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        let mut extra: Vec<String> = entries.flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .filter(|name| name != FRAME_MANIFEST_FILE && !expected.iter().any(|(_, file_name)| file_name == name))
+            .collect();
+        extra.sort();
+        warnings.extend(extra.into_iter().map(|name| format!("undeclared file {} (not used by any layer)", name)));
+    }
+
+    if problems.is_empty() {
+        FrameLoadResult { frame: Ok(Frame { details, layers, digest: digest.finalize().to_vec() }), warnings }
+    } else {
+        FrameLoadResult { frame: Err(problems), warnings }
     }
-    frames
-}
\ No newline at end of file
+}
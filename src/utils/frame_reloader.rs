@@ -0,0 +1,92 @@
+use std::{path::Path, sync::Arc, time::{Duration, SystemTime}};
+use log::{error, info, warn};
+
+use crate::utils::frame_loader::{load_frames, FrameStore};
+
+/// Reloads frames on SIGHUP and (when `watch_interval` is above 0) whenever files under `frames_path` change.
+pub fn spawn_frame_reloader(store: Arc<FrameStore>, frames_path: String, watch_interval: u64, strict: bool) {
+    #[cfg(unix)]
+    {
+        let store = store.clone();
+        let frames_path = frames_path.clone();
+        tokio::spawn(async move {
+            let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+                Ok(signal) => signal,
+                Err(e) => {
+                    error!("Failed to install SIGHUP handler, frames can't be reloaded on signal: {}", e);
+                    return;
+                }
+            };
+
+            while hangup.recv().await.is_some() {
+                info!("Received SIGHUP, reloading frames...");
+                reload(&store, &frames_path, strict).await;
+            }
+        });
+    }
+
+    if watch_interval == 0 {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut last_snapshot = snapshot(&frames_path).await;
+        let mut interval = tokio::time::interval(Duration::from_secs(watch_interval));
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let current = snapshot(&frames_path).await;
+            if current == last_snapshot {
+                continue;
+            }
+
+            info!("Detected changes in {}, reloading frames...", frames_path);
+            last_snapshot = current;
+            reload(&store, &frames_path, strict).await;
+        }
+    });
+}
+
+async fn reload(store: &Arc<FrameStore>, frames_path: &str, strict: bool) {
+    let path = frames_path.to_string();
+    match tokio::task::spawn_blocking(move || load_frames(&path, strict)).await {
+        Ok(Ok(registry)) => {
+            store.replace(registry);
+            info!("Successfully reloaded frames.");
+        }
+        Ok(Err(e)) => error!("Failed to reload frames, keeping previously loaded set: {}", e),
+        Err(e) => error!("Frame reload task crashed, keeping previously loaded set: {}", e),
+    }
+}
+
+/// Cheap fingerprint of frames directory (file paths, sizes and modification times).
+async fn snapshot(frames_path: &str) -> Vec<(String, u64, Option<SystemTime>)> {
+    let path = frames_path.to_string();
+    tokio::task::spawn_blocking(move || {
+        let mut entries = Vec::new();
+        collect_files(Path::new(&path), &mut entries);
+        entries.sort();
+        entries
+    }).await.unwrap_or_default()
+}
+
+fn collect_files(dir: &Path, entries: &mut Vec<(String, u64, Option<SystemTime>)>) {
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => {
+            warn!("Failed to scan {} for frame changes: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else { continue };
+        if metadata.is_dir() {
+            collect_files(&path, entries);
+        } else {
+            entries.push((path.display().to_string(), metadata.len(), metadata.modified().ok()));
+        }
+    }
+}
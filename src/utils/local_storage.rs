@@ -0,0 +1,77 @@
+use std::{io, path::PathBuf};
+use async_trait::async_trait;
+use axum::body::Bytes;
+
+use crate::utils::render_store;
+use crate::utils::storage::{RenderStorage, StoredObject};
+
+/// Saved renders in local directory (`paths.renders`), written through temporary files and renamed into place.
+pub struct LocalStorage {
+    path: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        LocalStorage { path: path.into() }
+    }
+
+    async fn object(&self, key: &str) -> io::Result<Option<StoredObject>> {
+        match tokio::fs::metadata(self.path.join(key)).await {
+            Ok(file) if file.is_file() => {
+                let (modified, accessed) = render_store::file_times(&file);
+                Ok(Some(StoredObject { key: key.to_string(), size: file.len(), modified, accessed }))
+            }
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl RenderStorage for LocalStorage {
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>> {
+        match tokio::fs::read(self.path.join(key)).await {
+            Ok(content) => Ok(Some(content.into())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn put(&self, key: &str, content: Bytes) -> io::Result<()> {
+        render_store::write_atomic(&self.path, key, &content).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<bool> {
+        match tokio::fs::remove_file(self.path.join(key)).await {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list(&self, prefix: &str) -> io::Result<Vec<StoredObject>> {
+        let mut entries = tokio::fs::read_dir(&self.path).await?;
+        let mut objects = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let key = entry.file_name().to_string_lossy().to_string();
+            // File could have been removed in the meantime
+            if key.starts_with(prefix) {
+                objects.extend(self.object(&key).await?);
+            }
+        }
+        Ok(objects)
+    }
+
+    async fn head(&self, key: &str) -> io::Result<Option<StoredObject>> {
+        self.object(key).await
+    }
+
+    fn touch(&self, key: &str) {
+        render_store::touch(self.path.join(key));
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        Some(self.path.join(key))
+    }
+}
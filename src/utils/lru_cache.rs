@@ -0,0 +1,120 @@
+use std::{collections::{BTreeMap, HashMap}, hash::Hash, sync::{atomic::{AtomicU64, Ordering}, Mutex}};
+use serde::Serialize;
+
+/// Thread safe LRU cache bounded by total size (in bytes) of stored values rather than their count.
+pub struct LruCache<K, V> {
+    inner: Mutex<Inner<K, V>>,
+    budget: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct Inner<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    order: BTreeMap<u64, K>, // last access tick -> key, first entry is least recently used
+    tick: u64,
+    size: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    tick: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub size: usize,
+    pub budget: usize,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(budget: usize) -> Self {
+        LruCache {
+            inner: Mutex::new(Inner { entries: HashMap::new(), order: BTreeMap::new(), tick: 0, size: 0 }),
+            budget,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.budget > 0
+    }
+
+    /// Returns cached value (marking it as recently used). Counts towards hit/miss statistics.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_if(key, |_| true)
+    }
+
+    /// Same as [`LruCache::get`], but entries rejected by `is_valid` are dropped and reported as miss.
+    pub fn get_if(&self, key: &K, is_valid: impl FnOnce(&V) -> bool) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        let inner = &mut *inner;
+        inner.tick += 1;
+
+        if inner.entries.get(key).is_some_and(|entry| !is_valid(&entry.value)) {
+            inner.remove(key);
+        }
+
+        match inner.entries.get_mut(key) {
+            Some(entry) => {
+                inner.order.remove(&entry.tick);
+                entry.tick = inner.tick;
+                inner.order.insert(entry.tick, key.clone());
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.value.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Stores value, evicting least recently used entries until it fits. Values bigger than whole budget are not cached.
+    pub fn insert(&self, key: K, value: V, size: usize) {
+        if size > self.budget {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.remove(&key);
+
+        while inner.size + size > self.budget {
+            let Some((_, oldest)) = inner.order.pop_first() else { break };
+            if let Some(entry) = inner.entries.remove(&oldest) {
+                inner.size -= entry.size;
+            }
+        }
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.order.insert(tick, key.clone());
+        inner.entries.insert(key, Entry { value, size, tick });
+        inner.size += size;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: inner.entries.len(),
+            size: inner.size,
+            budget: self.budget,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> Inner<K, V> {
+    fn remove(&mut self, key: &K) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+            self.size -= entry.size;
+        }
+    }
+}
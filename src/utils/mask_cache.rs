@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use image::{ImageBuffer, Rgba};
+
+use crate::models::FrameId;
+use crate::utils::lru_cache::{CacheStats, LruCache};
+
+pub type Mask = ImageBuffer<Rgba<u8>, Vec<u8>>;
+
+#[derive(Hash, Eq, PartialEq, Clone, Copy)]
+pub struct MaskKey {
+    pub generation: u64, // frame registry generation, so reloaded frames never reuse old masks
+    pub frame: FrameId,
+    pub kindled: bool,
+    pub dye: u32,
+}
+
+/// Recolored frame masks. Bot uses small set of dyes over and over, so recoloring each of them once is enough.
+pub struct MaskCache {
+    cache: LruCache<MaskKey, Arc<Mask>>,
+}
+
+impl MaskCache {
+    pub fn new(budget: usize) -> Self {
+        MaskCache { cache: LruCache::new(budget) }
+    }
+
+    pub fn get_or_recolor<E>(&self, key: MaskKey, recolor: impl FnOnce() -> Result<Mask, E>) -> Result<Arc<Mask>, E> {
+        if !self.cache.is_enabled() {
+            return recolor().map(Arc::new);
+        }
+
+        if let Some(mask) = self.cache.get(&key) {
+            return Ok(mask);
+        }
+
+        // Failed (e.g. cancelled) recolor is never cached
+        let mask = Arc::new(recolor()?);
+        self.cache.insert(key, mask.clone(), mask.as_raw().len());
+        Ok(mask)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
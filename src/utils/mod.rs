@@ -1,8 +1,41 @@
 mod album_render;
+mod art_cache;
+mod art_resolver;
 mod card_render;
+mod deadline;
+mod disk_cache;
 mod fan_render;
+mod fingerprint;
 mod frame_loader;
-pub use album_render::render_album;
-pub use card_render::render_card;
-pub use fan_render::render_fan;
-pub use frame_loader::load_frames;
\ No newline at end of file
+mod frame_reloader;
+mod local_storage;
+mod lru_cache;
+mod mask_cache;
+mod render_context;
+mod render_hash;
+mod render_pool;
+mod render_retention;
+pub mod render_store;
+mod response_cache;
+mod s3_storage;
+mod single_flight;
+pub mod signing;
+mod storage;
+pub use album_render::{render_album, validate_album};
+pub use art_cache::ArtCache;
+pub use card_render::{card_size, render_card};
+pub use deadline::Deadline;
+pub use disk_cache::{DiskCache, DiskCacheStats};
+pub use fan_render::{render_fan, validate_fan};
+pub use fingerprint::render_fingerprint;
+pub use frame_loader::{load_frames, FrameRegistry, FrameStore};
+pub use frame_reloader::spawn_frame_reloader;
+pub use lru_cache::CacheStats;
+pub use mask_cache::MaskCache;
+pub use render_context::RenderContext;
+pub use render_hash::{decode_hash, encode_hash, HashError};
+pub use render_pool::{PoolStats, RenderPool};
+pub use render_retention::{spawn_render_retention, RenderRetention, RetentionReport};
+pub use response_cache::ResponseCache;
+pub use single_flight::{FlightStats, SingleFlight};
+pub use storage::{open_storage, RenderStorage};
\ No newline at end of file
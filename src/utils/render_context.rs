@@ -0,0 +1,13 @@
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::utils::{ArtCache, FrameRegistry, MaskCache};
+
+/// Everything render functions need, captured once per request (so frame reload never affects render in progress).
+#[derive(Clone)]
+pub struct RenderContext {
+    pub config: Arc<Config>,
+    pub frames: Arc<FrameRegistry>,
+    pub art_cache: Arc<ArtCache>,
+    pub mask_cache: Arc<MaskCache>,
+}
@@ -0,0 +1,189 @@
+use std::io::{Read, Write};
+use base64::{engine::general_purpose::{STANDARD_NO_PAD, URL_SAFE_NO_PAD}, Engine as _};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Payload is MessagePack encoded request.
+const VERSION_MSGPACK: u8 = 1;
+/// Payload is deflate compressed MessagePack encoded request.
+const VERSION_MSGPACK_DEFLATE: u8 = 2;
+
+pub enum HashError {
+    /// Hash is not valid base64 (in any supported alphabet) or uses unknown version.
+    Malformed,
+    /// Hash was decoded properly but its content does not follow API structure.
+    InvalidStructure,
+    /// Decoded (or decompressed) hash is bigger than allowed.
+    TooLarge,
+}
+
+/// Encodes request into compact, URL safe hash: `base64url(version byte + payload)`.
+/// Compressed payload is used only when it's actually smaller.
+pub fn encode_hash<T: Serialize>(value: &T) -> String {
+    let payload = rmp_serde::to_vec(value).expect("render requests are always serializable");
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    let compressed = encoder.write_all(&payload).and_then(|_| encoder.finish()).ok();
+
+    let mut bytes = Vec::with_capacity(payload.len() + 1);
+    match compressed {
+        Some(compressed) if compressed.len() < payload.len() => {
+            bytes.push(VERSION_MSGPACK_DEFLATE);
+            bytes.extend_from_slice(&compressed);
+        }
+        _ => {
+            bytes.push(VERSION_MSGPACK);
+            bytes.extend_from_slice(&payload);
+        }
+    }
+
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes versioned hash produced by [`encode_hash`]. Legacy hashes (standard base64 of plain JSON) are still accepted.
+/// Neither decoded nor decompressed payload can be bigger than `max_size` bytes.
+pub fn decode_hash<T: DeserializeOwned>(hash: &str, max_size: usize) -> Result<T, HashError> {
+    // Checked before decoding, so oversized hash is never allocated
+    if hash.len() / 4 * 3 > max_size {
+        return Err(HashError::TooLarge);
+    }
+
+    let bytes = match URL_SAFE_NO_PAD.decode(hash) {
+        Ok(bytes) => bytes,
+        // Legacy hashes use standard alphabet which can contain `+` and `/`
+        Err(_) => STANDARD_NO_PAD.decode(hash).map_err(|_| HashError::Malformed)?,
+    };
+
+    match bytes.first() {
+        // Legacy JSON hashes always start with `{`
+        Some(b'{') => serde_json::from_slice(&bytes).map_err(|_| HashError::InvalidStructure),
+        Some(&VERSION_MSGPACK) => rmp_serde::from_slice(&bytes[1..]).map_err(|_| HashError::InvalidStructure),
+        Some(&VERSION_MSGPACK_DEFLATE) => {
+            let mut payload = Vec::new();
+            DeflateDecoder::new(&bytes[1..]).take(max_size as u64 + 1).read_to_end(&mut payload).map_err(|_| HashError::Malformed)?;
+            if payload.len() > max_size {
+                return Err(HashError::TooLarge);
+            }
+            rmp_serde::from_slice(&payload).map_err(|_| HashError::InvalidStructure)
+        }
+        _ => Err(HashError::Malformed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CardRenderRequestData, FanRenderRequestData, FrameId, Placement};
+
+    const MAX_SIZE: usize = 64 * 1024;
+
+    fn card(id: u32) -> CardRenderRequestData {
+        CardRenderRequestData {
+            id,
+            variant: 1,
+            dye: 0xFF8000,
+            kindled: true,
+            frame_type: FrameId(1),
+            offset_x: Some(-5),
+            offset_y: None,
+            placement: Some(Placement::Fit),
+            custom_art: None,
+            save_name: Some("card_1".to_string()),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(hash: &str) -> T {
+        match decode_hash(hash, MAX_SIZE) {
+            Ok(value) => value,
+            Err(_) => panic!("hash {} failed to decode", hash),
+        }
+    }
+
+    fn version(hash: &str) -> u8 {
+        URL_SAFE_NO_PAD.decode(hash).unwrap()[0]
+    }
+
+    #[test]
+    fn decodes_legacy_json_hash() {
+        let json = r#"{"id":1,"variant":0,"dye":16777215,"kindled":true,"frame_type":1,"offset_x":-5,"offset_y":null,"save_name":"x??"}"#;
+        let hash = STANDARD_NO_PAD.encode(json);
+        // Standard alphabet, not valid base64url
+        assert!(hash.contains('/'));
+
+        let card: CardRenderRequestData = decode(&hash);
+        assert_eq!((card.id, card.variant, card.dye, card.kindled, card.frame_type), (1, 0, 0xFFFFFF, true, FrameId(1)));
+        assert_eq!((card.offset_x, card.offset_y), (Some(-5), None));
+        assert_eq!(card.placement, None);
+        assert_eq!(card.save_name.as_deref(), Some("x??"));
+
+        let fan: FanRenderRequestData = decode(&STANDARD_NO_PAD.encode(r#"{"cards":[{"id":2,"variant":1,"dye":0,"kindled":false,"frame_type":0}]}"#));
+        assert_eq!(fan.cards.len(), 1);
+        assert_eq!(fan.cards[0].id, 2);
+        assert!(fan.save_name.is_none());
+    }
+
+    #[test]
+    fn round_trips_uncompressed_hash() {
+        let hash = encode_hash(&card(7));
+        assert_eq!(version(&hash), VERSION_MSGPACK);
+
+        let decoded: CardRenderRequestData = decode(&hash);
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(card(7)).unwrap());
+        // Canonical, so re-encoding gives the same hash
+        assert_eq!(encode_hash(&decoded), hash);
+    }
+
+    #[test]
+    fn round_trips_compressed_hash() {
+        let fan = FanRenderRequestData { cards: (0..20).map(card).collect(), save_name: Some("fan_1".to_string()) };
+        let hash = encode_hash(&fan);
+        assert_eq!(version(&hash), VERSION_MSGPACK_DEFLATE);
+
+        let decoded: FanRenderRequestData = decode(&hash);
+        assert_eq!(serde_json::to_value(&decoded).unwrap(), serde_json::to_value(&fan).unwrap());
+        assert_eq!(encode_hash(&decoded), hash);
+    }
+
+    #[test]
+    fn decodes_hash_missing_trailing_fields() {
+        // Hash made before `offset_x` and every later field were added
+        let mut bytes = vec![VERSION_MSGPACK];
+        bytes.extend(rmp_serde::to_vec(&(3u32, 2u8, 255u32, false, 0u8)).unwrap());
+
+        let card: CardRenderRequestData = decode(&URL_SAFE_NO_PAD.encode(bytes));
+        assert_eq!((card.id, card.variant, card.dye, card.kindled, card.frame_type), (3, 2, 255, false, FrameId(0)));
+        assert!(card.offset_x.is_none() && card.offset_y.is_none() && card.placement.is_none() && card.custom_art.is_none() && card.save_name.is_none());
+    }
+
+    #[test]
+    fn rejects_oversized_hash() {
+        let fan = FanRenderRequestData { cards: (0..20).map(card).collect(), save_name: None };
+        let hash = encode_hash(&fan);
+        // Hash itself fits, but decompressed payload doesn't
+        assert!(hash.len() < rmp_serde::to_vec(&fan).unwrap().len());
+        assert!(matches!(decode_hash::<FanRenderRequestData>(&hash, hash.len()), Err(HashError::TooLarge)));
+
+        // Rejected by length alone, before decoding
+        let hash = "A".repeat(MAX_SIZE * 2);
+        assert!(matches!(decode_hash::<FanRenderRequestData>(&hash, MAX_SIZE), Err(HashError::TooLarge)));
+    }
+
+    #[test]
+    fn rejects_decompression_bomb() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![0; 16 * 1024 * 1024]).unwrap();
+        let mut bytes = vec![VERSION_MSGPACK_DEFLATE];
+        bytes.extend(encoder.finish().unwrap());
+
+        let hash = URL_SAFE_NO_PAD.encode(bytes);
+        assert!(hash.len() < MAX_SIZE);
+        assert!(matches!(decode_hash::<FanRenderRequestData>(&hash, MAX_SIZE), Err(HashError::TooLarge)));
+    }
+
+    #[test]
+    fn rejects_malformed_hash() {
+        assert!(matches!(decode_hash::<CardRenderRequestData>("not a hash!", MAX_SIZE), Err(HashError::Malformed)));
+        assert!(matches!(decode_hash::<CardRenderRequestData>(&URL_SAFE_NO_PAD.encode([9, 1, 2]), MAX_SIZE), Err(HashError::Malformed)));
+        assert!(matches!(decode_hash::<CardRenderRequestData>(&URL_SAFE_NO_PAD.encode([VERSION_MSGPACK, 0xc0]), MAX_SIZE), Err(HashError::InvalidStructure)));
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc};
+use log::error;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::Serialize;
+use tokio::sync::{oneshot, Semaphore};
+
+use crate::error::RenderError;
+
+/// Dedicated rayon pool for CPU heavy render work, so it never blocks tokio workers. Number of jobs that can
+/// be running or waiting for a thread at once is bounded by `queue_size`, anything above is rejected right away.
+pub struct RenderPool {
+    pool: ThreadPool,
+    slots: Arc<Semaphore>,
+    queue_size: usize,
+    retry_after: u64,
+    rejected: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct PoolStats {
+    pub threads: usize,
+    pub queued: usize, // running and waiting jobs
+    pub queue_size: usize,
+    pub rejected: u64,
+}
+
+impl RenderPool {
+    /// `threads` set to 0 uses one thread per CPU core.
+    pub fn new(threads: usize, queue_size: usize, retry_after: u64) -> Result<Self, String> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("render-{}", i))
+            // Default handler aborts whole process, failed job is reported through its dropped channel instead
+            .panic_handler(|_| error!("Render job panicked, its request will fail with internal error."))
+            .build()
+            .map_err(|e| format!("failed to build render thread pool: {}", e))?;
+
+        Ok(RenderPool {
+            pool,
+            slots: Arc::new(Semaphore::new(queue_size)),
+            queue_size,
+            retry_after,
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    /// Runs `job` on render pool (parallel iterators inside it use the same pool). Fails with
+    /// [`RenderError::Overloaded`] without waiting when queue is full.
+    pub async fn run<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Result<T, RenderError> {
+        let Ok(slot) = self.slots.clone().try_acquire_owned() else {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(RenderError::Overloaded(self.retry_after));
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        self.pool.spawn(move || {
+            let _slot = slot;
+            let _ = sender.send(job());
+        });
+
+        receiver.await.map_err(|_| RenderError::Internal("render job failed unexpectedly".to_string()))
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            threads: self.pool.current_num_threads(),
+            queued: self.queue_size - self.slots.available_permits(),
+            queue_size: self.queue_size,
+            rejected: self.rejected.load(Ordering::Relaxed),
+        }
+    }
+}
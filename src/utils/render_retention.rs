@@ -0,0 +1,271 @@
+use std::{cmp::Reverse, collections::HashSet, path::Path, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::{Duration, SystemTime, UNIX_EPOCH}};
+use log::{debug, error, info};
+use serde::Serialize;
+use tokio::sync::{Mutex, Notify};
+
+use crate::config::RetentionConfig;
+use crate::utils::render_store;
+use crate::utils::storage::{RenderStorage, StoredObject};
+use crate::utils::DiskCache;
+
+/// Temporary files and metadata sidecars without render are only removed once they are older than this, so files of
+/// save that is being written right now are never touched.
+const STALE_FILE_AGE: u64 = 60 * 60;
+
+/// Keeps saved renders and disk cache within `[retention]` limits: renders older than `max_age` are removed first,
+/// then least recently accessed ones until total size fits `max_size`. Runs periodically, on demand (admin API) and
+/// whenever writes since the last run could have pushed total size over the limit, so burst of saves can't fill
+/// the disk. Saved renders hard linked to disk cache entries are counted twice, so limit errs on the safe side.
+pub struct RenderRetention {
+    config: RetentionConfig,
+    storage: Arc<dyn RenderStorage>,
+    disk_cache: Option<Arc<DiskCache>>,
+    scanned_size: AtomicU64, // total size of renders found by the last run
+    written_size: AtomicU64, // bytes saved since the last run started
+    wake: Notify,
+    running: Mutex<()>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionReason {
+    Expired,
+    OverSize,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderLocation {
+    Saved, // name is save name
+    DiskCache, // name is render fingerprint
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Eviction {
+    pub name: String,
+    pub location: RenderLocation,
+    pub size: u64,
+    pub reason: EvictionReason,
+}
+
+impl RenderLocation {
+    fn label(&self) -> &'static str {
+        match self {
+            RenderLocation::Saved => "saved",
+            RenderLocation::DiskCache => "disk cached",
+        }
+    }
+}
+
+/// Saved render or disk cache entry retention can evict.
+struct StoredRender {
+    name: String,
+    location: RenderLocation,
+    size: u64,
+    modified: u64,
+    accessed: u64,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RetentionReport {
+    pub evicted: Vec<Eviction>,
+    pub freed: u64, // in bytes
+    pub kept: usize,
+    pub remaining: u64, // in bytes, total size of kept renders
+    pub stale_files: usize, // removed leftovers of interrupted saves
+}
+
+impl RenderRetention {
+    pub fn new(config: RetentionConfig, storage: Arc<dyn RenderStorage>, disk_cache: Option<Arc<DiskCache>>) -> Self {
+        RenderRetention {
+            config,
+            storage,
+            disk_cache,
+            scanned_size: AtomicU64::new(0),
+            written_size: AtomicU64::new(0),
+            wake: Notify::new(),
+            running: Mutex::new(()),
+        }
+    }
+
+    /// Called after every write (save or disk cache entry), wakes background task up when size limit may have been exceeded.
+    pub fn record_write(&self, size: u64) {
+        let written = self.written_size.fetch_add(size, Ordering::Relaxed) + size;
+        let max_size = self.max_size();
+        if max_size > 0 && self.scanned_size.load(Ordering::Relaxed) + written > max_size {
+            self.wake.notify_one();
+        }
+    }
+
+    pub async fn run(&self) -> std::io::Result<RetentionReport> {
+        let _running = self.running.lock().await;
+        self.written_size.store(0, Ordering::Relaxed);
+
+        let now = unix_now();
+        // Single listing of storage serves both cleanup of stale files and eviction, it's the expensive part on S3
+        let objects = self.storage.list("").await?;
+        let mut report = RetentionReport { stale_files: self.remove_stale_objects(&objects, now).await, ..Default::default() };
+        let mut renders = self.list(objects).await?;
+        if let Some(disk_cache) = &self.disk_cache {
+            for shard in disk_cache.shards().await? {
+                report.stale_files += remove_stale_files(&shard, now).await?;
+            }
+        }
+        let mut total: u64 = renders.iter().map(|render| render.size).sum();
+
+        if self.config.max_age > 0 {
+            let (expired, fresh) = renders.into_iter().partition(|render| now.saturating_sub(render.modified) > self.config.max_age);
+            renders = fresh;
+            for render in expired {
+                self.evict(render, EvictionReason::Expired, &mut total, &mut report).await;
+            }
+        }
+
+        // Evicting only down to the limit would make every following save trigger another run, so some headroom is left
+        let max_size = self.max_size();
+        if max_size > 0 && total > max_size {
+            let target = max_size / 10 * 9;
+            // Least recently accessed last, so they can be popped
+            renders.sort_by_key(|render| Reverse(render.accessed));
+            while total > target {
+                let Some(render) = renders.pop() else { break };
+                self.evict(render, EvictionReason::OverSize, &mut total, &mut report).await;
+            }
+        }
+        report.kept += renders.len();
+
+        report.remaining = total;
+        self.scanned_size.store(total, Ordering::Relaxed);
+
+        if report.evicted.is_empty() && report.stale_files == 0 {
+            debug!("Render retention evicted nothing, {} render(s) taking {} bytes are kept.", report.kept, total);
+        } else {
+            info!(
+                "Render retention evicted {} render(s) ({} bytes) and {} stale file(s), {} render(s) taking {} bytes are kept.",
+                report.evicted.len(), report.freed, report.stale_files, report.kept, total
+            );
+        }
+        Ok(report)
+    }
+
+    /// Saved renders without metadata sidecar weren't written by this service (or predate metadata), so they're never
+    /// evicted - storage can hold other files too.
+    async fn list(&self, objects: Vec<StoredObject>) -> std::io::Result<Vec<StoredRender>> {
+        let keys: HashSet<String> = objects.iter().map(|object| object.key.clone()).collect();
+        let mut renders: Vec<StoredRender> = objects.into_iter()
+            // Hidden objects are metadata sidecars and temporary files
+            .filter(|object| !object.key.starts_with('.') && keys.contains(&render_store::metadata_name(&object.key)))
+            .map(|object| StoredRender { name: object.key, location: RenderLocation::Saved, size: object.size, modified: object.modified, accessed: object.accessed })
+            .collect();
+        if let Some(disk_cache) = &self.disk_cache {
+            renders.extend(disk_cache.list().await?.into_iter().map(|render| StoredRender {
+                name: render.fingerprint,
+                location: RenderLocation::DiskCache,
+                size: render.size,
+                modified: render.modified,
+                accessed: render.accessed,
+            }));
+        }
+        Ok(renders)
+    }
+
+    async fn evict(&self, render: StoredRender, reason: EvictionReason, total: &mut u64, report: &mut RetentionReport) {
+        let result = match (render.location, &self.disk_cache) {
+            (RenderLocation::DiskCache, Some(disk_cache)) => disk_cache.remove(&render.name).await,
+            _ => render_store::delete_saved(self.storage.as_ref(), &render.name).await,
+        };
+        match result {
+            Ok(deleted) => {
+                *total -= render.size;
+                if deleted {
+                    info!("Evicted {} render {} ({} bytes, {:?}, last accessed at {})", render.location.label(), render.name, render.size, reason, render.accessed);
+                    report.freed += render.size;
+                    report.evicted.push(Eviction { name: render.name, location: render.location, size: render.size, reason });
+                }
+            }
+            Err(e) => {
+                error!("Failed to evict {} render {}. Received error: {}", render.location.label(), render.name, e);
+                report.kept += 1;
+            }
+        }
+    }
+
+    /// Removes temporary files left behind by interrupted saves and metadata sidecars whose render is gone.
+    async fn remove_stale_objects(&self, objects: &[StoredObject], now: u64) -> usize {
+        let keys: HashSet<&str> = objects.iter().map(|object| object.key.as_str()).collect();
+        let mut removed = 0;
+        for object in objects {
+            let stale = match object.key.strip_prefix('.') {
+                Some(name) if name.ends_with(".tmp") => true,
+                Some(name) => name.strip_suffix(".json").is_some_and(|save_name| !keys.contains(save_name)),
+                None => false,
+            };
+            if stale && now.saturating_sub(object.modified) > STALE_FILE_AGE {
+                match self.storage.delete(&object.key).await {
+                    Ok(_) => removed += 1,
+                    Err(e) => error!("Failed to remove stale file {}. Received error: {}", object.key, e),
+                }
+            }
+        }
+        removed
+    }
+
+    /// Whether any limit is set, without them there's nothing for background task to enforce.
+    fn enabled(&self) -> bool {
+        self.config.max_size > 0 || self.config.max_age > 0
+    }
+
+    fn max_size(&self) -> u64 {
+        self.config.max_size * 1024 * 1024
+    }
+}
+
+/// Enforces retention on startup, then every `interval` seconds and whenever [`RenderRetention::record_write`] asks for it.
+/// Nothing is spawned when no limit is set, so storage isn't scanned for nothing (admin API can still run it on demand).
+pub fn spawn_render_retention(retention: Arc<RenderRetention>) {
+    if !retention.enabled() {
+        info!("Render retention is disabled (no max_size nor max_age set).");
+        return;
+    }
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = retention.run().await {
+                error!("Failed to enforce render retention: {}", e);
+            }
+
+            match retention.config.interval {
+                0 => retention.wake.notified().await,
+                interval => {
+                    let _ = tokio::time::timeout(Duration::from_secs(interval), retention.wake.notified()).await;
+                }
+            }
+        }
+    });
+}
+
+/// Removes temporary files left behind by interrupted disk cache writes.
+async fn remove_stale_files(dir: &Path, now: u64) -> std::io::Result<usize> {
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    let mut removed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !(name.starts_with('.') && name.ends_with(".tmp")) {
+            continue;
+        }
+
+        let Ok(metadata) = entry.metadata().await else { continue };
+        let modified = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|time| time.as_secs()).unwrap_or_default();
+        if metadata.is_file() && now.saturating_sub(modified) > STALE_FILE_AGE {
+            match tokio::fs::remove_file(entry.path()).await {
+                Ok(()) => removed += 1,
+                Err(e) => error!("Failed to remove stale file {}. Received error: {}", entry.path().display(), e),
+            }
+        }
+    }
+    Ok(removed)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default()
+}
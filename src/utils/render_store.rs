@@ -0,0 +1,266 @@
+use std::{fs::{File, FileTimes}, io, path::{Path, PathBuf}, sync::atomic::{AtomicU64, Ordering}, time::{SystemTime, UNIX_EPOCH}};
+use axum::body::Bytes;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt as _;
+
+use crate::error::RenderError;
+use crate::utils::fingerprint::hex;
+use crate::utils::storage::{RenderStorage, StoredObject};
+
+const MAX_SAVE_NAME_LENGTH: usize = 128;
+
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Stored next to saved render (in hidden `.{save_name}.json` sidecar), so it can be told where render came from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RenderMetadata {
+    pub kind: String,
+    pub hash: String, // canonical render hash
+    pub fingerprint: String,
+    pub spec: serde_json::Value, // request render was made from
+    #[serde(default)]
+    pub digest: String, // sha256 of saved render, set by `write_saved`
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SavedRender {
+    pub name: String,
+    pub size: u64,
+    pub modified: u64, // unix timestamp in seconds
+    pub accessed: u64, // unix timestamp in seconds, of last time it was served (or saved)
+    pub metadata: Option<RenderMetadata>, // missing for renders saved before metadata was introduced
+}
+
+/// `save_name` comes straight from clients, so it must be plain file name: letters, digits, `_`, `-` and `.`
+/// (but not leading one, which also rules out `..` and hidden files).
+pub fn validate_save_name(save_name: &str) -> Result<(), RenderError> {
+    let valid = !save_name.is_empty()
+        && save_name.len() <= MAX_SAVE_NAME_LENGTH
+        && !save_name.starts_with('.')
+        && save_name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if !valid {
+        return Err(RenderError::InvalidRequest(format!(
+            "save_name must be 1-{} characters long, consist of letters, digits, '_', '-' and '.' and not start with '.'",
+            MAX_SAVE_NAME_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// Reads saved render, but only when it was rendered with given fingerprint - same name could have been used for
+/// different request before, or render could be made from older assets. Render is also checked against digest in its
+/// metadata, so it's never served next to metadata of another save (interrupted or racing one), nor for request of
+/// another kind. Missing or outdated render is a regular cache miss, any other failure is logged and treated as miss too.
+pub async fn read_saved(storage: &dyn RenderStorage, save_name: &str, kind: &str, fingerprint: &str) -> Option<Bytes> {
+    let metadata = read_metadata(storage, save_name).await
+        .filter(|metadata| metadata.kind == kind && metadata.fingerprint == fingerprint)?;
+    match storage.get(save_name).await {
+        Ok(Some(png)) if digest(&png) == metadata.digest => {
+            storage.touch(save_name);
+            Some(png)
+        }
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Failed to read saved render {}. Received error: {}", save_name, e);
+            None
+        }
+    }
+}
+
+/// Whether `save_name` already holds `png` of given kind rendered with given fingerprint, according to its metadata.
+pub async fn is_saved(storage: &dyn RenderStorage, save_name: &str, kind: &str, fingerprint: &str, png: &[u8]) -> bool {
+    read_metadata(storage, save_name).await
+        .is_some_and(|metadata| metadata.kind == kind && metadata.fingerprint == fingerprint && metadata.digest == digest(png))
+}
+
+/// Reads file and marks it as accessed.
+pub async fn read_file(path: PathBuf) -> Option<Bytes> {
+    match tokio::fs::read(&path).await {
+        Ok(png) => {
+            touch(path);
+            Some(png.into())
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => {
+            warn!("Failed to read render at path: {}. Received error: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Saves render (and its metadata) as a whole, readers never see partially written render. When `linked` file with
+/// the same content is given (disk cache entry) and storage is local, render is hard linked to it instead, so it
+/// takes no extra space. Metadata goes last and carries digest of render, so failed or racing saves can't pair it
+/// with another render (see [`read_saved`]).
+pub async fn write_saved(storage: &dyn RenderStorage, save_name: &str, png: Bytes, metadata: &RenderMetadata, linked: Option<&Path>) -> io::Result<()> {
+    let metadata = RenderMetadata { digest: digest(&png), ..metadata.clone() };
+    let linked = match (linked, storage.local_path(save_name)) {
+        (Some(linked), Some(path)) => match link_atomic(&path, linked).await {
+            Ok(()) => true,
+            // Entry was evicted in the meantime
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            // E.g. disk cache is on different filesystem
+            Err(e) => {
+                warn!("Failed to link saved render {} to {}, writing it as a copy. Received error: {}", save_name, linked.display(), e);
+                false
+            }
+        },
+        _ => false,
+    };
+    if !linked {
+        storage.put(save_name, png).await?;
+    }
+    storage.put(&metadata_name(save_name), serde_json::to_vec(&metadata)?.into()).await
+}
+
+/// Saved renders with names matching `pattern` (glob with `*` and `?`), sorted by name. Reading metadata of
+/// each render can be skipped when only object details are needed.
+pub async fn list_saved(storage: &dyn RenderStorage, pattern: &str, with_metadata: bool) -> io::Result<Vec<SavedRender>> {
+    let mut objects: Vec<StoredObject> = storage.list("").await?
+        .into_iter()
+        // Hidden objects are metadata sidecars and temporary files
+        .filter(|object| !object.key.starts_with('.') && glob_match(pattern, &object.key))
+        .collect();
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let mut renders = Vec::with_capacity(objects.len());
+    for object in objects {
+        let metadata = match with_metadata {
+            true => read_metadata(storage, &object.key).await,
+            false => None,
+        };
+        renders.push(saved_render(object, metadata));
+    }
+    Ok(renders)
+}
+
+pub async fn saved_info(storage: &dyn RenderStorage, save_name: &str) -> io::Result<Option<SavedRender>> {
+    let Some(object) = storage.head(save_name).await? else { return Ok(None) };
+    let metadata = read_metadata(storage, save_name).await;
+    Ok(Some(saved_render(object, metadata)))
+}
+
+fn saved_render(object: StoredObject, metadata: Option<RenderMetadata>) -> SavedRender {
+    SavedRender {
+        name: object.key,
+        size: object.size,
+        modified: object.modified,
+        accessed: object.accessed,
+        metadata,
+    }
+}
+
+async fn read_metadata(storage: &dyn RenderStorage, save_name: &str) -> Option<RenderMetadata> {
+    let metadata = storage.get(&metadata_name(save_name)).await.ok()??;
+    serde_json::from_slice(&metadata).ok()
+}
+
+/// Removes saved render together with its metadata. Returns whether there was anything to remove.
+pub async fn delete_saved(storage: &dyn RenderStorage, save_name: &str) -> io::Result<bool> {
+    let _ = storage.delete(&metadata_name(save_name)).await;
+    storage.delete(save_name).await
+}
+
+/// Marks file as just accessed, in background, so request doesn't wait for it.
+pub fn touch(path: PathBuf) {
+    tokio::task::spawn_blocking(move || {
+        let result = File::open(&path).and_then(|file| file.set_times(FileTimes::new().set_accessed(SystemTime::now())));
+        // Render could have been evicted in the meantime
+        match result {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => warn!("Failed to update access time of saved render at path: {}. Received error: {}", path.display(), e),
+        }
+    });
+}
+
+/// Modification and last access time of file as unix timestamps in seconds.
+pub fn file_times(file: &std::fs::Metadata) -> (u64, u64) {
+    let modified = unix_seconds(file.modified());
+    // Access time is only bumped explicitly (see `touch`), so filesystem mounted with noatime works too
+    (modified, unix_seconds(file.accessed()).max(modified))
+}
+
+fn unix_seconds(time: io::Result<SystemTime>) -> u64 {
+    time.ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|time| time.as_secs()).unwrap_or_default()
+}
+
+fn digest(png: &[u8]) -> String {
+    hex(&Sha256::digest(png))
+}
+
+/// Leading dot makes sure sidecar name never collides with valid save name.
+pub fn metadata_name(save_name: &str) -> String {
+    format!(".{}.json", save_name)
+}
+
+/// Writes `name` file in `dir` into temporary file first and renames it over final path, so readers never see
+/// partially written file.
+pub async fn write_atomic(dir: &Path, name: &str, content: &[u8]) -> io::Result<()> {
+    let temp_path = temp_path(dir, name);
+    let result = write_and_rename(&temp_path, &dir.join(name), content).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+async fn link_atomic(path: &Path, linked: &Path) -> io::Result<()> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(io::Error::other(format!("{} is not a file path", path.display())));
+    };
+    let temp_path = temp_path(dir, &name.to_string_lossy());
+    tokio::fs::hard_link(linked, &temp_path).await?;
+    let result = tokio::fs::rename(&temp_path, path).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+/// Leading dot makes sure temporary name never collides with valid save name.
+fn temp_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!(".{}.{}-{}.tmp", name.trim_start_matches('.'), std::process::id(), NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)))
+}
+
+async fn write_and_rename(temp_path: &Path, path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = tokio::fs::File::create(temp_path).await?;
+    file.write_all(content).await?;
+    file.sync_all().await?;
+    drop(file);
+    tokio::fs::rename(temp_path, path).await
+}
+
+/// Matches whole `name` against `pattern`, where `*` matches any run of characters and `?` single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None; // position of last `*` in pattern and name position it currently covers up to
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, covered)) => {
+                    p = star + 1;
+                    n = covered + 1;
+                    backtrack = Some((star, covered + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
@@ -0,0 +1,33 @@
+use axum::body::Bytes;
+
+use crate::utils::lru_cache::{CacheStats, LruCache};
+
+/// Encoded PNG renders keyed by their render fingerprint (see [`crate::utils::render_fingerprint`]). Fingerprint
+/// changes together with any asset render depends on, so entries never need to be invalidated explicitly.
+pub struct ResponseCache {
+    cache: LruCache<String, Bytes>,
+}
+
+impl ResponseCache {
+    pub fn new(budget: usize) -> Self {
+        ResponseCache { cache: LruCache::new(budget) }
+    }
+
+    pub fn get(&self, fingerprint: &str) -> Option<Bytes> {
+        if !self.cache.is_enabled() {
+            return None;
+        }
+        self.cache.get(&fingerprint.to_string())
+    }
+
+    pub fn insert(&self, fingerprint: String, png: Bytes) {
+        if self.cache.is_enabled() {
+            let size = png.len();
+            self.cache.insert(fingerprint, png, size);
+        }
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+}
@@ -0,0 +1,175 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::error::RenderError;
+
+/// Signatures are truncated HMAC-SHA256 (first 16 bytes), which keeps signed URLs short.
+const SIGNATURE_LENGTH: usize = 16;
+
+/// Signs render hash. Result (`{hash}.{signature}` or `{hash}.{expires}.{signature}`, where `expires` is unix
+/// timestamp in seconds) can be used in place of plain hash in any GET render route.
+pub fn sign_hash(secret: &str, hash: &str, expires: Option<u64>) -> String {
+    let message = match expires {
+        Some(expires) => format!("{}.{}", hash, expires),
+        None => hash.to_string(),
+    };
+    format!("{}.{}", message, sign(secret, message.as_bytes()))
+}
+
+/// Signature of arbitrary message (e.g. POST body) - base64url of truncated HMAC-SHA256.
+pub fn sign(secret: &str, message: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    URL_SAFE_NO_PAD.encode(&mac.finalize().into_bytes()[..SIGNATURE_LENGTH])
+}
+
+/// Splits (optionally) signed hash into plain hash, expiry and signature.
+pub fn split_signed_hash(signed: &str) -> (&str, Option<&str>, Option<&str>) {
+    let mut parts = signed.splitn(3, '.');
+    let hash = parts.next().unwrap_or_default();
+    match (parts.next(), parts.next()) {
+        (Some(signature), None) => (hash, None, Some(signature)),
+        (Some(expires), Some(signature)) => (hash, Some(expires), Some(signature)),
+        _ => (hash, None, None),
+    }
+}
+
+/// Outcome of successful [`verify`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    /// Request carried valid signature, expiring at given unix timestamp (in seconds) if any.
+    Signed { expires: Option<u64> },
+    /// Request wasn't signed (or signatures are not configured) and that's allowed.
+    Unsigned,
+}
+
+/// Verifies signature of `message` (with optional expiry appended as `.{expires}`). When `secret` is not
+/// configured signatures are ignored. Missing signature is accepted unless `required` is set.
+pub fn verify(secret: Option<&str>, required: bool, message: &[u8], expires: Option<&str>, signature: Option<&str>) -> Result<Verification, RenderError> {
+    let Some(secret) = secret else {
+        return Ok(Verification::Unsigned);
+    };
+    let Some(signature) = signature else {
+        if required {
+            return Err(RenderError::MissingSignature("this service requires signed render requests".to_string()));
+        }
+        return Ok(Verification::Unsigned);
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    if let Some(expires) = expires {
+        mac.update(b".");
+        mac.update(expires.as_bytes());
+    }
+
+    let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| RenderError::InvalidSignature("signature is malformed".to_string()))?;
+    if signature.len() != SIGNATURE_LENGTH || mac.verify_truncated_left(&signature).is_err() {
+        return Err(RenderError::InvalidSignature("signature doesn't match request".to_string()));
+    }
+
+    let expires = match expires {
+        Some(expires) => Some(expires.parse::<u64>().map_err(|_| RenderError::InvalidSignature("signature expiry is malformed".to_string()))?),
+        None => None,
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    if expires.is_some_and(|expires| now > expires) {
+        return Err(RenderError::ExpiredSignature("signed request has expired".to_string()));
+    }
+
+    Ok(Verification::Signed { expires })
+}
+
+/// Compares secret tokens (e.g. admin token) without leaking how much of them matched through timing.
+pub fn tokens_match(expected: &str, provided: &str) -> bool {
+    let mut mac = Hmac::<Sha256>::new_from_slice(expected.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(expected.as_bytes());
+    let expected_tag = mac.finalize().into_bytes();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(expected.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(provided.as_bytes());
+    mac.verify_slice(&expected_tag).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "secret";
+    const HASH: &str = "AZoBAM4A_wAAwgDAwMDAwA";
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    /// Verifies signed hash the way GET route does.
+    fn verify_hash(signed: &str, required: bool) -> Result<Verification, RenderError> {
+        let (hash, expires, signature) = split_signed_hash(signed);
+        verify(Some(SECRET), required, hash.as_bytes(), expires, signature)
+    }
+
+    #[test]
+    fn splits_signed_hash() {
+        assert_eq!(split_signed_hash(HASH), (HASH, None, None));
+        assert_eq!(split_signed_hash("hash.signature"), ("hash", None, Some("signature")));
+        assert_eq!(split_signed_hash("hash.123.signature"), ("hash", Some("123"), Some("signature")));
+        // Only the first two dots split, so malformed signature is still checked (and rejected)
+        assert_eq!(split_signed_hash("hash.123.sig.nature"), ("hash", Some("123"), Some("sig.nature")));
+    }
+
+    #[test]
+    fn accepts_valid_signatures() {
+        assert_eq!(verify_hash(&sign_hash(SECRET, HASH, None), true).unwrap(), Verification::Signed { expires: None });
+
+        let expires = now() + 60;
+        assert_eq!(verify_hash(&sign_hash(SECRET, HASH, Some(expires)), true).unwrap(), Verification::Signed { expires: Some(expires) });
+
+        let body = br#"{"id":1}"#;
+        assert_eq!(verify(Some(SECRET), true, body, None, Some(&sign(SECRET, body))).unwrap(), Verification::Signed { expires: None });
+    }
+
+    #[test]
+    fn rejects_tampered_signatures() {
+        let signed = sign_hash(SECRET, HASH, None);
+        let (_, _, signature) = split_signed_hash(&signed);
+
+        assert!(matches!(verify_hash(&format!("{}A.{}", HASH, signature.unwrap()), false), Err(RenderError::InvalidSignature(_))));
+        assert!(matches!(verify_hash(&sign_hash("other secret", HASH, None), false), Err(RenderError::InvalidSignature(_))));
+
+        // Expiry is covered by signature, so it can't be extended
+        let signed = sign_hash(SECRET, HASH, Some(now() + 60));
+        let (_, expires, signature) = split_signed_hash(&signed);
+        let extended = format!("{}.{}.{}", HASH, expires.unwrap().parse::<u64>().unwrap() + 3600, signature.unwrap());
+        assert!(matches!(verify_hash(&extended, false), Err(RenderError::InvalidSignature(_))));
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        let signed = sign_hash(SECRET, HASH, None);
+        assert!(matches!(verify_hash(&signed[..signed.len() - 2], false), Err(RenderError::InvalidSignature(_))));
+        assert!(matches!(verify_hash(&format!("{}.not*base64", HASH), false), Err(RenderError::InvalidSignature(_))));
+        assert!(matches!(verify_hash(&format!("{}.", HASH), false), Err(RenderError::InvalidSignature(_))));
+
+        let message = format!("{}.soon", HASH);
+        let signed = format!("{}.{}", message, sign(SECRET, message.as_bytes()));
+        assert!(matches!(verify_hash(&signed, false), Err(RenderError::InvalidSignature(_))));
+    }
+
+    #[test]
+    fn rejects_expired_signatures() {
+        let signed = sign_hash(SECRET, HASH, Some(now() - 1));
+        assert!(matches!(verify_hash(&signed, false), Err(RenderError::ExpiredSignature(_))));
+    }
+
+    #[test]
+    fn handles_missing_signatures() {
+        assert!(matches!(verify_hash(HASH, true), Err(RenderError::MissingSignature(_))));
+        assert_eq!(verify_hash(HASH, false).unwrap(), Verification::Unsigned);
+        // Expiry alone doesn't make request signed
+        assert_eq!(verify(Some(SECRET), false, HASH.as_bytes(), Some("1"), None).unwrap(), Verification::Unsigned);
+        // Without secret signatures are not checked at all
+        assert_eq!(verify(None, true, HASH.as_bytes(), None, Some("garbage")).unwrap(), Verification::Unsigned);
+    }
+}
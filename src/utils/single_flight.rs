@@ -0,0 +1,99 @@
+use std::{collections::HashMap, future::Future, sync::{atomic::{AtomicU64, Ordering}, Mutex}};
+use serde::Serialize;
+use tokio::{sync::watch, task::AbortHandle};
+
+/// Deduplicates concurrent work by key: first caller starts the work, everyone who comes in before it finishes
+/// waits for it and gets (clone of) the same result. Work runs in its own task, so it carries on when the caller that
+/// started it is dropped (e.g. its client disconnected) and is only aborted once nobody waits for it anymore.
+pub struct SingleFlight<V> {
+    in_flight: Mutex<HashMap<String, Flight<V>>>,
+    next_id: AtomicU64,
+    executed: AtomicU64,
+    coalesced: AtomicU64,
+}
+
+struct Flight<V> {
+    id: u64, // tells apart flights of the same key started one after another
+    result: watch::Receiver<Option<V>>,
+    waiters: usize,
+    task: AbortHandle,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct FlightStats {
+    pub in_flight: usize,
+    pub executed: u64,
+    pub coalesced: u64, // callers served by work of another caller (work that was saved)
+}
+
+impl<V: Clone + Send + Sync + 'static> SingleFlight<V> {
+    pub fn new() -> Self {
+        SingleFlight {
+            in_flight: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            executed: AtomicU64::new(0),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+
+    /// `None` when work panicked.
+    pub async fn run<F>(&self, key: String, work: impl FnOnce() -> F) -> Option<V>
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let (id, mut result) = {
+            let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+            match in_flight.get_mut(&key) {
+                Some(flight) => {
+                    flight.waiters += 1;
+                    self.coalesced.fetch_add(1, Ordering::Relaxed);
+                    (flight.id, flight.result.clone())
+                }
+                None => {
+                    let (sender, result) = watch::channel(None);
+                    let work = work();
+                    let task = tokio::spawn(async move {
+                        let _ = sender.send(Some(work.await));
+                    }).abort_handle();
+                    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    in_flight.insert(key.clone(), Flight { id, result: result.clone(), waiters: 1, task });
+                    self.executed.fetch_add(1, Ordering::Relaxed);
+                    (id, result)
+                }
+            }
+        };
+
+        let _waiter = Waiter { flight: self, key, id };
+        result.wait_for(Option::is_some).await.ok().and_then(|value| value.clone())
+    }
+
+    pub fn stats(&self) -> FlightStats {
+        FlightStats {
+            in_flight: self.in_flight.lock().unwrap_or_else(|e| e.into_inner()).len(),
+            executed: self.executed.load(Ordering::Relaxed),
+            coalesced: self.coalesced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Leaves flight once caller is done with it, even when it's dropped mid-way. Finished work is not shared with later
+/// callers (they have caches for that), unfinished one is aborted when the last caller waiting for it leaves.
+struct Waiter<'a, V> {
+    flight: &'a SingleFlight<V>,
+    key: String,
+    id: u64,
+}
+
+impl<V> Drop for Waiter<'_, V> {
+    fn drop(&mut self) {
+        let mut in_flight = self.flight.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(flight) = in_flight.get_mut(&self.key).filter(|flight| flight.id == self.id) else { return };
+        flight.waiters -= 1;
+        if flight.waiters == 0 || flight.result.borrow().is_some() {
+            if flight.waiters == 0 {
+                flight.task.abort();
+            }
+            in_flight.remove(&self.key);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::{io, path::PathBuf, sync::Arc};
+use async_trait::async_trait;
+use axum::body::Bytes;
+
+use crate::config::{Config, StorageBackend};
+use crate::utils::local_storage::LocalStorage;
+use crate::utils::s3_storage::S3Storage;
+
+/// Where saved renders live (`[storage]` section of config). Keys are plain file names - save names and their hidden
+/// metadata sidecars - so every backend can hold them as they are.
+#[async_trait]
+pub trait RenderStorage: Send + Sync {
+    /// `None` when object doesn't exist.
+    async fn get(&self, key: &str) -> io::Result<Option<Bytes>>;
+
+    /// Replaces object as a whole, readers never see partially written content.
+    async fn put(&self, key: &str, content: Bytes) -> io::Result<()>;
+
+    /// Returns whether there was anything to delete.
+    async fn delete(&self, key: &str) -> io::Result<bool>;
+
+    /// Objects with keys starting with `prefix`, in no particular order.
+    async fn list(&self, prefix: &str) -> io::Result<Vec<StoredObject>>;
+
+    /// `None` when object doesn't exist.
+    async fn head(&self, key: &str) -> io::Result<Option<StoredObject>>;
+
+    /// Marks object as just accessed (for LRU retention), backends that don't track access ignore it.
+    fn touch(&self, _key: &str) {}
+
+    /// Path of object on local filesystem, lets saved renders be hard linked to disk cache entries.
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub size: u64,
+    pub modified: u64, // unix timestamp in seconds
+    pub accessed: u64, // unix timestamp in seconds, same as modified when backend doesn't track access
+}
+
+pub fn open_storage(config: &Config) -> Result<Arc<dyn RenderStorage>, String> {
+    match config.storage.backend {
+        StorageBackend::Local => Ok(Arc::new(LocalStorage::new(&config.paths.renders))),
+        StorageBackend::S3 => Ok(Arc::new(S3Storage::new(&config.storage)?)),
+    }
+}